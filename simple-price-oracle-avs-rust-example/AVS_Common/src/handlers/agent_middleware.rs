@@ -0,0 +1,430 @@
+use async_openai::types::CreateChatCompletionRequest;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use crate::handlers::openai::{Completion, LlmError, OpenAIAgent, Round, TokenSink};
+use crate::settings::env_or;
+
+const DEFAULT_LLM_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_LLM_MAX_RETRIES: u32 = 3;
+const DEFAULT_LLM_RETRY_BASE_MS: u64 = 500;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_LLM_RATE_PER_MINUTE: f64 = 60.0;
+const DEFAULT_LLM_RATE_BURST: f64 = 10.0;
+const DEFAULT_LLM_BREAKER_THRESHOLD: u32 = 5;
+const DEFAULT_LLM_BREAKER_COOLDOWN_SECS: u64 = 30;
+
+/// Settings of the agent middleware, read from the service environment.
+#[derive(Debug, Clone)]
+pub struct MiddlewareConfig {
+    /// Deadline of each attempt at one round (`LLM_TIMEOUT_SECS`)
+    pub timeout: Duration,
+    /// Retries after a transient failure (`LLM_MAX_RETRIES`)
+    pub max_retries: u32,
+    /// Base of the exponential retry delay (`LLM_RETRY_BASE_MS`)
+    pub retry_base: Duration,
+    /// Calls allowed per minute and per backend, 0 for no limit (`LLM_RATE_PER_MINUTE`)
+    pub rate_per_minute: f64,
+    /// Calls allowed at once after an idle period (`LLM_RATE_BURST`)
+    pub rate_burst: f64,
+    /// Consecutive failed calls that open the circuit, 0 to never open it (`LLM_BREAKER_THRESHOLD`)
+    pub breaker_threshold: u32,
+    /// How long an open circuit refuses calls (`LLM_BREAKER_COOLDOWN_SECS`)
+    pub breaker_cooldown: Duration,
+}
+
+impl MiddlewareConfig {
+    pub fn from_env() -> Self {
+        MiddlewareConfig {
+            timeout: Duration::from_secs(env_or("LLM_TIMEOUT_SECS", DEFAULT_LLM_TIMEOUT_SECS)),
+            max_retries: env_or("LLM_MAX_RETRIES", DEFAULT_LLM_MAX_RETRIES),
+            retry_base: Duration::from_millis(env_or("LLM_RETRY_BASE_MS", DEFAULT_LLM_RETRY_BASE_MS)),
+            rate_per_minute: env_or("LLM_RATE_PER_MINUTE", DEFAULT_LLM_RATE_PER_MINUTE),
+            rate_burst: env_or("LLM_RATE_BURST", DEFAULT_LLM_RATE_BURST),
+            breaker_threshold: env_or("LLM_BREAKER_THRESHOLD", DEFAULT_LLM_BREAKER_THRESHOLD),
+            breaker_cooldown: Duration::from_secs(env_or("LLM_BREAKER_COOLDOWN_SECS", DEFAULT_LLM_BREAKER_COOLDOWN_SECS)),
+        }
+    }
+}
+
+/// The full stack around a backend: the circuit breaker, then retries, then
+/// the rate limit and the deadline of each attempt.
+pub type Resilient<C> = CircuitBreaker<Retry<RateLimit<Timeout<C>>>>;
+
+/// Wraps every round `agent` sends to the model in the middleware stack, so
+/// tool calls answered in earlier rounds are never repeated by a retry. Rate
+/// limits and circuit state are shared by every agent of the same `backend`.
+pub fn wrap(agent: OpenAIAgent, backend: &str, config: &MiddlewareConfig) -> OpenAIAgent {
+    agent.map_completion(|completion| Box::new(resilient(completion, backend, config)))
+}
+
+/// Wraps one completion in the middleware stack.
+pub fn resilient<C: Completion>(completion: C, backend: &str, config: &MiddlewareConfig) -> Resilient<C> {
+    let completion = Timeout { inner: completion, timeout: config.timeout };
+    let completion = RateLimit {
+        inner: completion,
+        backend: backend.to_string(),
+        per_second: config.rate_per_minute / 60.0,
+        burst: config.rate_burst.max(1.0),
+    };
+    let completion = Retry { inner: completion, max_retries: config.max_retries, base: config.retry_base };
+    CircuitBreaker {
+        inner: completion,
+        backend: backend.to_string(),
+        threshold: config.breaker_threshold,
+        cooldown: config.breaker_cooldown,
+    }
+}
+
+/// Fails an attempt that takes longer than `timeout`.
+pub struct Timeout<C> {
+    inner: C,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl<C: Completion> Completion for Timeout<C> {
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        tokio::time::timeout(self.timeout, self.inner.create(request, tokens))
+            .await
+            .map_err(|_| LlmError::Timeout(self.timeout.as_secs()))?
+    }
+}
+
+/// Retries transient failures up to `max_retries` times, waiting a random
+/// delay of up to `base * 2^attempt` ("full jitter") between attempts.
+pub struct Retry<C> {
+    inner: C,
+    max_retries: u32,
+    base: Duration,
+}
+
+// Random fraction in [0, 1), good enough to spread retries apart
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    f64::from(nanos % 1_000_000) / 1_000_000.0
+}
+
+impl<C: Completion> Retry<C> {
+    // Relays the text an attempt streams to `tokens`, telling whether it streamed any
+    async fn attempt(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> (Result<Round, LlmError>, bool) {
+        let Some(tokens) = tokens else {
+            return (self.inner.create(request, None).await, false);
+        };
+        let (relay, mut relayed) = mpsc::unbounded_channel();
+        let create = async move {
+            let result = self.inner.create(request, Some(&relay)).await;
+            drop(relay);
+            result
+        };
+        let forward = async {
+            let mut streamed = false;
+            while let Some(text) = relayed.recv().await {
+                streamed = true;
+                let _ = tokens.send(text);
+            }
+            streamed
+        };
+        tokio::join!(create, forward)
+    }
+}
+
+#[async_trait]
+impl<C: Completion> Completion for Retry<C> {
+    // An attempt that already streamed part of its answer is not retried, as the
+    // caller would receive that text twice
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        let mut attempt = 0;
+        loop {
+            match self.attempt(request.clone(), tokens).await {
+                (Err(e), true) if e.is_transient() => {
+                    warn!(code = e.code(), error = %e, "Not retrying an LLM call whose answer was partly streamed");
+                    return Err(e);
+                }
+                (Err(e), false) if e.is_transient() && attempt < self.max_retries => {
+                    let ceiling = self.base.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_RETRY_DELAY);
+                    let delay = ceiling.mul_f64(jitter());
+                    attempt += 1;
+                    warn!(attempt, delay_ms = delay.as_millis() as u64, code = e.code(), error = %e, "Retrying LLM call");
+                    tokio::time::sleep(delay).await;
+                }
+                (result, _) => return result,
+            }
+        }
+    }
+}
+
+/// Token bucket of one backend, refilled continuously.
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+static BUCKETS: OnceLock<Mutex<HashMap<String, Bucket>>> = OnceLock::new();
+
+/// Holds each attempt until the backend's token bucket has a token.
+pub struct RateLimit<C> {
+    inner: C,
+    backend: String,
+    per_second: f64,
+    burst: f64,
+}
+
+impl<C> RateLimit<C> {
+    // Takes a token, or returns how long to wait for the next one
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut buckets = BUCKETS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets
+            .entry(self.backend.clone())
+            .or_insert(Bucket { tokens: self.burst, refilled_at: now });
+
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second))
+        }
+    }
+
+}
+
+#[async_trait]
+impl<C: Completion> Completion for RateLimit<C> {
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        if self.per_second > 0.0 {
+            while let Some(wait) = self.try_acquire() {
+                info!(backend = %self.backend, wait_ms = wait.as_millis() as u64, "LLM call rate limited");
+                tokio::time::sleep(wait).await;
+            }
+        }
+        self.inner.create(request, tokens).await
+    }
+}
+
+/// Circuit state of one backend.
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+static CIRCUITS: OnceLock<Mutex<HashMap<String, Circuit>>> = OnceLock::new();
+
+fn circuits() -> &'static Mutex<HashMap<String, Circuit>> {
+    CIRCUITS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Stops calling a backend for `cooldown` once `threshold` calls in a row
+/// failed transiently. After the cooldown calls go through again, and the next
+/// transient failure opens the circuit again.
+pub struct CircuitBreaker<C> {
+    inner: C,
+    backend: String,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+#[async_trait]
+impl<C: Completion> Completion for CircuitBreaker<C> {
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        if self.threshold == 0 {
+            return self.inner.create(request, tokens).await;
+        }
+
+        {
+            let mut circuits = circuits().lock().unwrap();
+            let circuit = circuits.entry(self.backend.clone()).or_default();
+            match circuit.open_until {
+                Some(until) if Instant::now() < until => return Err(LlmError::CircuitOpen(self.backend.clone())),
+                // Half open: let this call probe the backend
+                Some(_) => circuit.open_until = None,
+                None => {}
+            }
+        }
+
+        let result = self.inner.create(request, tokens).await;
+
+        let mut circuits = circuits().lock().unwrap();
+        let circuit = circuits.entry(self.backend.clone()).or_default();
+        match &result {
+            Ok(_) => circuit.failures = 0,
+            Err(e) if e.is_transient() => {
+                circuit.failures += 1;
+                if circuit.failures >= self.threshold {
+                    circuit.open_until = Some(Instant::now() + self.cooldown);
+                    warn!(backend = %self.backend, failures = circuit.failures, "LLM circuit opened");
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionToolType, CompletionUsage, FunctionCall};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use crate::handlers::openai::{Agent, Message, ToolExecutor, ToolSpec};
+    use crate::services::usage::TokenUsage;
+
+    // Asks for one tool call, then fails once transiently, then answers
+    struct FlakyCompletion {
+        calls: AtomicUsize,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Completion for FlakyCompletion {
+        async fn create(&self, _request: CreateChatCompletionRequest, _tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+            tokio::time::sleep(self.delay).await;
+            let round = match self.calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Round {
+                    content: None,
+                    tool_calls: vec![ChatCompletionMessageToolCall {
+                        id: "call_1".to_string(),
+                        r#type: ChatCompletionToolType::Function,
+                        function: FunctionCall { name: "get_price".to_string(), arguments: r#"{"pair":"ETHUSDT"}"#.to_string() },
+                    }],
+                    usage: Some(CompletionUsage {
+                        prompt_tokens: 100,
+                        completion_tokens: 20,
+                        total_tokens: 120,
+                        prompt_tokens_details: None,
+                        completion_tokens_details: None,
+                    }),
+                },
+                1 => return Err(LlmError::Timeout(1)),
+                _ => Round { content: Some("answer".to_string()), tool_calls: Vec::new(), usage: None },
+            };
+            Ok(round)
+        }
+    }
+
+    // Streams part of an answer, then fails transiently
+    struct CutStream {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Completion for CutStream {
+        async fn create(&self, _request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(tokens) = tokens {
+                let _ = tokens.send("Sta".to_string());
+            }
+            Err(LlmError::Timeout(1))
+        }
+    }
+
+    struct CountingTools {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for CountingTools {
+        fn specs(&self) -> Vec<ToolSpec> {
+            vec![ToolSpec { name: "get_price", description: "price", parameters: json!({}) }]
+        }
+
+        async fn call(&self, _name: &str, _arguments: &Value) -> Value {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            json!({ "price": "2500" })
+        }
+    }
+
+    fn config(timeout: Duration, max_retries: u32) -> MiddlewareConfig {
+        MiddlewareConfig {
+            timeout,
+            max_retries,
+            retry_base: Duration::from_millis(1),
+            rate_per_minute: 0.0,
+            rate_burst: 1.0,
+            breaker_threshold: 0,
+            breaker_cooldown: Duration::ZERO,
+        }
+    }
+
+    fn agent(delay: Duration, config: &MiddlewareConfig) -> OpenAIAgent {
+        let completion = FlakyCompletion { calls: AtomicUsize::new(0), delay };
+        OpenAIAgent::new("test".to_string(), "test-model".to_string(), 0.0)
+            .map_completion(|_| Box::new(resilient(completion, "test", config)))
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![Message { role: "user".to_string(), content: "strategy".to_string() }]
+    }
+
+    #[tokio::test]
+    async fn retries_only_the_failed_round() {
+        let tools = CountingTools { calls: AtomicUsize::new(0) };
+        let response = agent(Duration::ZERO, &config(Duration::from_secs(5), 1))
+            .chat_with_tools(messages(), &tools)
+            .await
+            .unwrap();
+
+        assert_eq!(response.response, "answer");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(tools.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn deadline_applies_to_each_round() {
+        // Three rounds of 40ms each take longer than the 100ms deadline, but each round is within it
+        let tools = CountingTools { calls: AtomicUsize::new(0) };
+        let response = agent(Duration::from_millis(40), &config(Duration::from_millis(100), 1))
+            .chat_with_tools(messages(), &tools)
+            .await
+            .unwrap();
+
+        assert_eq!(response.response, "answer");
+    }
+
+    #[tokio::test]
+    async fn fails_once_retries_are_spent() {
+        let tools = CountingTools { calls: AtomicUsize::new(0) };
+        let metered = Arc::new(Mutex::new(Vec::new()));
+        let sink = metered.clone();
+        let mut agent = agent(Duration::ZERO, &config(Duration::from_secs(5), 0));
+        agent.meter_usage(Arc::new(move |usage: &TokenUsage| sink.lock().unwrap().push(usage.clone())));
+
+        let result = agent.chat_with_tools(messages(), &tools).await;
+
+        assert!(matches!(result, Err(LlmError::Timeout(_))));
+        // The round before the failed one is still metered
+        let metered = metered.lock().unwrap();
+        assert_eq!(metered.len(), 1);
+        assert_eq!((metered[0].prompt_tokens, metered[0].completion_tokens), (100, 20));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_partly_streamed_answer() {
+        let retry = Retry { inner: CutStream { calls: AtomicUsize::new(0) }, max_retries: 2, base: Duration::from_millis(1) };
+        let (tokens, mut streamed) = mpsc::unbounded_channel();
+
+        let result = retry.create(CreateChatCompletionRequest::default(), Some(&tokens)).await;
+
+        assert!(matches!(result, Err(LlmError::Timeout(_))));
+        assert_eq!(retry.inner.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(streamed.recv().await.as_deref(), Some("Sta"));
+        assert!(streamed.try_recv().is_err());
+
+        // Without a listener nothing was streamed, so the failures are retried
+        assert!(retry.create(CreateChatCompletionRequest::default(), None).await.is_err());
+        assert_eq!(retry.inner.calls.load(Ordering::SeqCst), 4);
+    }
+}
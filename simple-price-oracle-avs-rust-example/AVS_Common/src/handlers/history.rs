@@ -0,0 +1,22 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use crate::response::ApiResponse;
+use crate::services::task_history::{self, ExportFormat, TaskQuery};
+
+// Handler for the `/tasks` endpoint, listing recorded tasks newest first
+//
+// Filters by `from`/`to` (unix seconds), `task_definition_id`, `outcome` and
+// `performer`; `format=csv` or `format=jsonl` exports the records instead.
+pub async fn get_tasks(query: web::Query<TaskQuery>) -> HttpResponse {
+    let records = task_history::query(&query);
+
+    match query.format {
+        ExportFormat::Json => ApiResponse::ok(json!({ "tasks": records }), "Task history"),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv")
+            .body(task_history::to_csv(&records)),
+        ExportFormat::Jsonl => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .body(task_history::to_jsonl(&records)),
+    }
+}
@@ -0,0 +1,256 @@
+use alloy_primitives::keccak256;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::env;
+use std::path::PathBuf;
+use tokio::fs;
+use tracing::{debug, warn};
+use crate::handlers::openai::{self, Agent, ChatResponse, LlmError, Message, TokenSink, ToolExecutor};
+use crate::services::usage::TokenUsage;
+use crate::settings::env_or;
+
+const DEFAULT_LLM_CACHE_DIR: &str = "llm_cache";
+
+/// How `CachedAgent` uses its cache, from `LLM_CACHE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Every request goes to the model
+    Off,
+    /// Cached responses are served, and misses are asked to the model and stored
+    On,
+    /// Every request goes to the model and its response is stored, replacing any cached one
+    Record,
+    /// Only cached responses are served; a miss fails with `LLM_CACHE_MISS`
+    Replay,
+}
+
+impl CacheMode {
+    pub fn from_env() -> Self {
+        match env::var("LLM_CACHE").unwrap_or_default().as_str() {
+            "on" => CacheMode::On,
+            "record" => CacheMode::Record,
+            "replay" => CacheMode::Replay,
+            _ => CacheMode::Off,
+        }
+    }
+}
+
+/// Agent answering identical requests with the same stored response.
+///
+/// Responses are kept as `<LLM_CACHE_DIR>/<key>.json`, where the key hashes the
+/// model parameters, the messages and the tools offered. A cached response
+/// comes with the tool calls it was made with; the tools are not called again.
+pub struct CachedAgent<A: Agent> {
+    inner: A,
+    mode: CacheMode,
+    dir: PathBuf,
+    stream: Option<TokenSink>,
+}
+
+impl<A: Agent + Send + Sync> CachedAgent<A> {
+    pub fn new(inner: A, mode: CacheMode, dir: PathBuf) -> Self {
+        CachedAgent { inner, mode, dir, stream: None }
+    }
+
+    /// Wraps `inner` with the mode in `LLM_CACHE` and the directory in `LLM_CACHE_DIR`.
+    pub fn from_env(inner: A) -> Self {
+        let dir = env_or("LLM_CACHE_DIR", DEFAULT_LLM_CACHE_DIR.to_string());
+        Self::new(inner, CacheMode::from_env(), PathBuf::from(dir))
+    }
+
+    fn key(&self, messages: &[Message], tools: Option<&dyn ToolExecutor>) -> String {
+        let request = json!({
+            "params": self.inner.params(),
+            "messages": messages,
+            "tools": tools.map(|tools| tools.specs()).unwrap_or_default(),
+        });
+        keccak256(request.to_string().as_bytes()).to_string()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    // A missing or unreadable entry is a miss
+    async fn load(&self, key: &str) -> Option<ChatResponse> {
+        let text = fs::read_to_string(self.path(key)).await.ok()?;
+        serde_json::from_str(&text)
+            .inspect_err(|e| warn!(%key, error = %e, "Ignoring unreadable LLM cache entry"))
+            .ok()
+    }
+
+    // Failing to store a response is logged, it never fails the request. The entry is
+    // written aside and renamed into place, so a concurrent load never reads half of it.
+    async fn store(&self, key: &str, response: &ChatResponse) {
+        let path = self.path(key);
+        let partial = self.dir.join(format!("{}.json.{}", key, uuid::Uuid::new_v4()));
+        let stored = async {
+            let text = serde_json::to_string_pretty(response).map_err(|e| e.to_string())?;
+            fs::create_dir_all(&self.dir).await.map_err(|e| e.to_string())?;
+            fs::write(&partial, text).await.map_err(|e| e.to_string())?;
+            fs::rename(&partial, &path).await.map_err(|e| e.to_string())
+        };
+        if let Err(e) = stored.await {
+            let _ = fs::remove_file(&partial).await;
+            warn!(%key, error = %e, "Failed to store LLM response");
+        }
+    }
+
+    async fn complete(&self, messages: Vec<Message>, tools: Option<&dyn ToolExecutor>) -> Result<ChatResponse, LlmError> {
+        if self.mode == CacheMode::Off {
+            return openai::ask(&self.inner, messages, tools).await;
+        }
+
+        let key = self.key(&messages, tools);
+        if matches!(self.mode, CacheMode::On | CacheMode::Replay) {
+            if let Some(mut response) = self.load(&key).await {
+                debug!(%key, "LLM cache hit");
+                // A cached answer costs nothing, and is streamed whole
+                response.usage = TokenUsage::default();
+                if let Some(tokens) = &self.stream {
+                    let _ = tokens.send(response.response.clone());
+                }
+                return Ok(response);
+            }
+            if self.mode == CacheMode::Replay {
+                return Err(LlmError::CacheMiss(key));
+            }
+        }
+
+        let response = openai::ask(&self.inner, messages, tools).await?;
+        self.store(&key, &response).await;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl<A: Agent + Send + Sync> Agent for CachedAgent<A> {
+    fn set_prompt(&mut self, prompt: String) -> &mut Self {
+        self.inner.set_prompt(prompt);
+        self
+    }
+
+    fn stream_to(&mut self, tokens: TokenSink) -> &mut Self {
+        self.stream = Some(tokens.clone());
+        self.inner.stream_to(tokens);
+        self
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<ChatResponse, LlmError> {
+        self.complete(messages, None).await
+    }
+
+    async fn chat_with_tools(&self, messages: Vec<Message>, tools: &dyn ToolExecutor) -> Result<ChatResponse, LlmError> {
+        self.complete(messages, Some(tools)).await
+    }
+
+    fn prompt(&self) -> &str {
+        self.inner.prompt()
+    }
+
+    fn params(&self) -> Value {
+        self.inner.params()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Answers with the number of calls made so far, so a cached answer is told apart
+    struct CountingAgent {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Agent for CountingAgent {
+        fn set_prompt(&mut self, _prompt: String) -> &mut Self {
+            self
+        }
+
+        async fn chat(&self, _messages: Vec<Message>) -> Result<ChatResponse, LlmError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(ChatResponse {
+                input_prompt: "prompt".to_string(),
+                response: format!("answer {}", call),
+                tool_calls: Vec::new(),
+                usage: TokenUsage { prompt_tokens: 10, completion_tokens: 5, cost_usd: 0.01 },
+            })
+        }
+
+        async fn chat_with_tools(&self, messages: Vec<Message>, _tools: &dyn ToolExecutor) -> Result<ChatResponse, LlmError> {
+            self.chat(messages).await
+        }
+
+        fn prompt(&self) -> &str {
+            ""
+        }
+
+        fn params(&self) -> Value {
+            json!({ "model": "counting" })
+        }
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("llm-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn agent(mode: CacheMode, dir: &Path, calls: &Arc<AtomicUsize>) -> CachedAgent<CountingAgent> {
+        CachedAgent::new(CountingAgent { calls: calls.clone() }, mode, dir.to_path_buf())
+    }
+
+    fn messages(text: &str) -> Vec<Message> {
+        vec![Message { role: "user".to_string(), content: text.to_string() }]
+    }
+
+    #[tokio::test]
+    async fn replays_what_was_recorded() {
+        let dir = cache_dir("replay");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorded = agent(CacheMode::Record, &dir, &calls).chat(messages("strategy")).await.unwrap();
+        assert_eq!(recorded.response, "answer 1");
+        assert_eq!(recorded.usage.prompt_tokens, 10);
+
+        let replayed = agent(CacheMode::Replay, &dir, &calls).chat(messages("strategy")).await.unwrap();
+        assert_eq!(replayed.response, "answer 1");
+        assert_eq!(replayed.usage, TokenUsage::default());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn replay_fails_on_a_miss() {
+        let dir = cache_dir("miss");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let missed = agent(CacheMode::Replay, &dir, &calls).chat(messages("strategy")).await;
+        assert!(matches!(missed, Err(LlmError::CacheMiss(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn record_replaces_and_on_serves_the_cache() {
+        let dir = cache_dir("record");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorder = agent(CacheMode::Record, &dir, &calls);
+        recorder.chat(messages("strategy")).await.unwrap();
+        recorder.chat(messages("strategy")).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let cached = agent(CacheMode::On, &dir, &calls);
+        assert_eq!(cached.chat(messages("strategy")).await.unwrap().response, "answer 2");
+        assert_eq!(cached.chat(messages("another")).await.unwrap().response, "answer 3");
+        assert_eq!(cached.chat(messages("another")).await.unwrap().response, "answer 3");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Only whole entries are left in the cache
+        let entries = std::fs::read_dir(&dir).unwrap().filter_map(Result::ok).count();
+        assert_eq!(entries, 2);
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::sync::OnceLock;
+use crate::settings::env_or;
+
+const DEFAULT_MODEL_POLICY_PATH: &str = "model_policy.json";
+
+/// Failure to find a model allowed for a task.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum PolicyError {
+    #[error("Model {model} is not allowed for task definition {task_definition_id}")]
+    NotAllowed { model: String, task_definition_id: i32 },
+    #[error("Model policy could not be loaded: {0}")]
+    Invalid(String),
+}
+
+impl PolicyError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyError::NotAllowed { .. } => "MODEL_NOT_ALLOWED",
+            PolicyError::Invalid(_) => "MODEL_POLICY_INVALID",
+        }
+    }
+}
+
+/// A model allowed for agent tasks, pinned to an exact version and sampling
+/// parameters. It is recorded in the proof, so validators re-run the same model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPin {
+    /// Name tasks ask for, such as `gpt-4o`
+    pub model: String,
+    /// Exact model version sent to the backend, such as `gpt-4o-2024-08-06`
+    pub version: String,
+    pub temperature: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+/// Models allowed by default, and by task definitions that list their own.
+#[derive(Debug, Default, Deserialize)]
+pub struct ModelPolicy {
+    #[serde(default)]
+    pub default: Vec<ModelPin>,
+    #[serde(default)]
+    pub task_definitions: BTreeMap<i32, Vec<ModelPin>>,
+}
+
+impl ModelPolicy {
+    /// Models allowed for `task_definition_id`.
+    pub fn allowed(&self, task_definition_id: i32) -> &[ModelPin] {
+        self.task_definitions.get(&task_definition_id).unwrap_or(&self.default)
+    }
+
+    /// Every model version the policy pins, by default or for a task definition.
+    pub fn versions(&self) -> BTreeSet<&str> {
+        self.default
+            .iter()
+            .chain(self.task_definitions.values().flatten())
+            .map(|pin| pin.version.as_str())
+            .collect()
+    }
+
+    /// Pin of `model` for `task_definition_id`, asked for by name or by exact version.
+    pub fn resolve(&self, task_definition_id: i32, model: &str) -> Result<ModelPin, PolicyError> {
+        self.allowed(task_definition_id)
+            .iter()
+            .find(|pin| pin.model == model || pin.version == model)
+            .cloned()
+            .ok_or_else(|| PolicyError::NotAllowed { model: model.to_string(), task_definition_id })
+    }
+}
+
+static POLICY: OnceLock<Result<ModelPolicy, PolicyError>> = OnceLock::new();
+
+/// Policy read from `MODEL_POLICY_PATH` on first use. A missing or unreadable
+/// policy allows no model.
+pub fn policy() -> Result<&'static ModelPolicy, PolicyError> {
+    POLICY
+        .get_or_init(|| {
+            let path = env_or("MODEL_POLICY_PATH", DEFAULT_MODEL_POLICY_PATH.to_string());
+            let text = fs::read_to_string(&path).map_err(|e| PolicyError::Invalid(format!("{}: {}", path, e)))?;
+            serde_json::from_str(&text).map_err(|e| PolicyError::Invalid(format!("{}: {}", path, e)))
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Pin of `model` for `task_definition_id` under the loaded policy.
+pub fn resolve(task_definition_id: i32, model: &str) -> Result<ModelPin, PolicyError> {
+    policy()?.resolve(task_definition_id, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pin(model: &str, version: &str) -> ModelPin {
+        ModelPin { model: model.to_string(), version: version.to_string(), temperature: 0.0, top_p: None, seed: Some(7) }
+    }
+
+    fn policy() -> ModelPolicy {
+        ModelPolicy {
+            default: vec![pin("gpt-4o", "gpt-4o-2024-08-06"), pin("gpt-4o-mini", "gpt-4o-mini-2024-07-18")],
+            task_definitions: BTreeMap::from([(2, vec![pin("gpt-4o", "gpt-4o-2024-11-20")])]),
+        }
+    }
+
+    #[test]
+    fn resolves_models_by_name_or_version() {
+        let policy = policy();
+        assert_eq!(policy.resolve(1, "gpt-4o").unwrap().version, "gpt-4o-2024-08-06");
+        assert_eq!(policy.resolve(1, "gpt-4o-mini-2024-07-18").unwrap().model, "gpt-4o-mini");
+        assert_eq!(policy.resolve(1, "gpt-4-turbo").unwrap_err().code(), "MODEL_NOT_ALLOWED");
+    }
+
+    #[test]
+    fn resolves_task_definition_pins_before_the_default() {
+        let policy = policy();
+        assert_eq!(policy.resolve(2, "gpt-4o").unwrap().version, "gpt-4o-2024-11-20");
+        // A task definition listing its own models allows only those
+        assert!(policy.resolve(2, "gpt-4o-mini").is_err());
+        assert!(policy.resolve(2, "gpt-4o-2024-08-06").is_err());
+        // Other task definitions fall back to the default
+        assert_eq!(policy.resolve(3, "gpt-4o").unwrap().version, "gpt-4o-2024-08-06");
+        assert_eq!(
+            policy.versions().into_iter().collect::<Vec<_>>(),
+            ["gpt-4o-2024-08-06", "gpt-4o-2024-11-20", "gpt-4o-mini-2024-07-18"]
+        );
+    }
+
+    #[test]
+    fn parses_the_shipped_policy() {
+        let policy: ModelPolicy = serde_json::from_str(include_str!("../../../model_policy.json")).unwrap();
+        assert!(!policy.default.is_empty());
+    }
+}
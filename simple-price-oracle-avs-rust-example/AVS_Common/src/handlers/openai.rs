@@ -0,0 +1,537 @@
+use async_openai::{
+    config::OpenAIConfig,
+    error::OpenAIError,
+    types::{
+        ChatCompletionRequestMessage, CreateChatCompletionRequest,
+        ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
+        ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
+        ChatCompletionTool, ChatCompletionToolType, FunctionObject,
+        ChatCompletionMessageToolCall, ChatCompletionStreamOptions, CompletionUsage, FunctionCall,
+    },
+    Client,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use crate::handlers::model_policy::ModelPin;
+use crate::handlers::prompts::RenderedPrompt;
+use crate::services::usage::{TokenUsage, UsageSink};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+// Rounds of tool calls answered before the model must give its final answer
+const MAX_TOOL_ROUNDS: usize = 5;
+
+/// Failure to get an answer from the LLM backend.
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("OPENAI_API_KEY is not set in environment variables")]
+    NotConfigured,
+    #[error("LLM request failed: {0}")]
+    Api(#[from] OpenAIError),
+    #[error("LLM returned no completion choices")]
+    EmptyResponse,
+    #[error("LLM was still calling tools after {0} rounds")]
+    ToolLimit(usize),
+    #[error("No cached LLM response for request {0}")]
+    CacheMiss(String),
+    #[error("LLM call did not finish within {0}s")]
+    Timeout(u64),
+    #[error("LLM backend {0} is failing, calls are suspended")]
+    CircuitOpen(String),
+}
+
+impl LlmError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LlmError::NotConfigured => "LLM_NOT_CONFIGURED",
+            LlmError::Api(_) => "LLM_UNAVAILABLE",
+            LlmError::EmptyResponse => "LLM_EMPTY_RESPONSE",
+            LlmError::ToolLimit(_) => "LLM_TOOL_LIMIT",
+            LlmError::CacheMiss(_) => "LLM_CACHE_MISS",
+            LlmError::Timeout(_) => "LLM_TIMEOUT",
+            LlmError::CircuitOpen(_) => "LLM_CIRCUIT_OPEN",
+        }
+    }
+
+    /// Whether the same call may succeed later: transport failures, timeouts,
+    /// rate limits and server errors, which come back without a type or code.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            LlmError::Api(OpenAIError::Reqwest(_)) | LlmError::Timeout(_) => true,
+            LlmError::Api(OpenAIError::ApiError(e)) => {
+                e.code.as_deref() == Some("rate_limit_exceeded") || (e.r#type.is_none() && e.code.is_none())
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub input_prompt: String,
+    pub response: String,
+    pub tool_calls: Vec<ToolCall>, // in the order the model made them
+    #[serde(default)]
+    pub usage: TokenUsage, // summed over every round of the call
+}
+
+/// A tool offered to the model, with a JSON schema of its arguments.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// A tool call made by the model and the output it was given. The transcript
+/// is recorded in the proof so validators can replay the same outputs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: Value,
+    pub output: Value,
+}
+
+/// Answers the tool calls of a model.
+#[async_trait]
+pub trait ToolExecutor: Send + Sync {
+    fn specs(&self) -> Vec<ToolSpec>;
+    /// Output of the tool, or a JSON `error` the model can read.
+    async fn call(&self, name: &str, arguments: &Value) -> Value;
+}
+
+/// Receives the text of an answer as the model writes it.
+pub type TokenSink = mpsc::UnboundedSender<String>;
+
+// Define the Agent trait
+#[async_trait]
+pub trait Agent {
+    fn set_prompt(&mut self, prompt: String) -> &mut Self;
+    /// Streams the text of later answers to `tokens` as it arrives. Agents that
+    /// cannot stream ignore it, and their answers only come back whole.
+    fn stream_to(&mut self, tokens: TokenSink) -> &mut Self {
+        drop(tokens);
+        self
+    }
+    async fn chat(&self, messages: Vec<Message>) -> Result<ChatResponse, LlmError>;
+    /// Chats while letting the model call the tools of `tools` until it answers.
+    async fn chat_with_tools(&self, messages: Vec<Message>, tools: &dyn ToolExecutor) -> Result<ChatResponse, LlmError>;
+    fn prompt(&self) -> &str;
+    /// Model and sampling parameters, which select the answer as much as the messages do.
+    fn params(&self) -> Value;
+}
+
+/// One completion of the model: its text, the tools it called and the tokens it used.
+pub struct Round {
+    pub(crate) content: Option<String>,
+    pub(crate) tool_calls: Vec<ChatCompletionMessageToolCall>,
+    pub(crate) usage: Option<CompletionUsage>,
+}
+
+/// Sends one completion request. `OpenAIAgent` makes one request per round of
+/// tool calls, and the agent middleware wraps each of them.
+#[async_trait]
+pub trait Completion: Send + Sync {
+    /// Completes `request`, streaming its text to `tokens` when given.
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError>;
+}
+
+#[async_trait]
+impl<C: Completion + ?Sized> Completion for Box<C> {
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        (**self).create(request, tokens).await
+    }
+}
+
+/// Completions of the OpenAI API, or of an OpenAI-compatible server.
+pub struct OpenAICompletion {
+    client: Client<OpenAIConfig>,
+}
+
+pub struct OpenAIAgent {
+    client: Client<OpenAIConfig>,
+    completion: Box<dyn Completion>,
+    model: String,
+    temperature: f32,
+    top_p: Option<f32>,
+    seed: Option<i64>,
+    prompt: String,
+    stream: Option<TokenSink>,
+    meter: Option<UsageSink>,
+}
+
+impl OpenAIAgent {
+    /// Agent calling the OpenAI API, or the OpenAI-compatible server at
+    /// `OPENAI_BASE_URL` when it is set, such as the `Mock_LLM` server.
+    pub fn new(api_key: String, model: String, temperature: f32) -> Self {
+        let mut config = OpenAIConfig::new().with_api_key(api_key);
+        if let Some(base_url) = env::var("OPENAI_BASE_URL").ok().filter(|url| !url.is_empty()) {
+            config = config.with_api_base(base_url.trim_end_matches('/'));
+        }
+        // Retries are left to the agent middleware, so the client's own backoff is turned off
+        let no_retry = backoff::ExponentialBackoffBuilder::new()
+            .with_max_elapsed_time(Some(Duration::ZERO))
+            .build();
+        let client = Client::with_config(config).with_backoff(no_retry);
+
+        Self {
+            completion: Box::new(OpenAICompletion { client: client.clone() }),
+            client,
+            model,
+            temperature,
+            top_p: None,
+            seed: None,
+            prompt: String::new(),
+            stream: None,
+            meter: None,
+        }
+    }
+
+    /// Agent authenticated with `OPENAI_API_KEY`, calling the pinned model
+    /// version with the pinned sampling parameters.
+    pub fn from_pin(pin: &ModelPin) -> Result<Self, LlmError> {
+        let mut agent = Self::from_env(pin.version.clone(), pin.temperature as f32)?;
+        agent.top_p = pin.top_p.map(|top_p| top_p as f32);
+        agent.seed = pin.seed;
+        Ok(agent)
+    }
+
+    /// Agent authenticated with `OPENAI_API_KEY`.
+    pub fn from_env(model: String, temperature: f32) -> Result<Self, LlmError> {
+        let api_key = env::var("OPENAI_API_KEY").map_err(|_| LlmError::NotConfigured)?;
+        Ok(Self::new(api_key, model, temperature))
+    }
+
+    /// Checks that the backend is reachable and serves the configured model.
+    pub async fn check_model(&self) -> Result<(), LlmError> {
+        self.client.models().retrieve(&self.model).await?;
+        Ok(())
+    }
+
+    /// Passes the usage of every round to `meter` as soon as it is spent, so it
+    /// is counted even when a later round fails.
+    pub fn meter_usage(&mut self, meter: UsageSink) -> &mut Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Replaces how each round is sent, such as to wrap it in the agent middleware.
+    pub fn map_completion(mut self, wrap: impl FnOnce(Box<dyn Completion>) -> Box<dyn Completion>) -> Self {
+        self.completion = wrap(self.completion);
+        self
+    }
+
+    #[tracing::instrument(name = "llm_call", skip_all, fields(model = %self.model))]
+    async fn complete(&self, messages: Vec<Message>, tools: Option<&dyn ToolExecutor>) -> Result<ChatResponse, LlmError> {
+        // Convert our Message type to the library's ChatCompletionRequestMessage type
+        debug!(count = messages.len(), "Sending messages to OpenAI");
+        
+        // Collect all message contents for the input_prompt
+        let input_prompt = messages.iter()
+            .map(|msg| format!("{}:\n{}", msg.role, msg.content))
+            .collect::<Vec<String>>()
+            .join("\n\n");
+            
+        for (i, msg) in messages.iter().enumerate() {
+            debug!(index = i, role = %msg.role, content = %msg.content, "OpenAI message");
+        }
+        
+        let mut request_messages: Vec<ChatCompletionRequestMessage> = messages
+            .into_iter()
+            .map(|msg| {
+                match msg.role.as_str() {
+                    "system" => ChatCompletionRequestMessage::System(
+                        ChatCompletionRequestSystemMessage {
+                            content: ChatCompletionRequestSystemMessageContent::Text(msg.content),
+                            name: None,
+                        }
+                    ),
+                    "assistant" => ChatCompletionRequestMessage::Assistant(
+                        ChatCompletionRequestAssistantMessage {
+                            content: Some(ChatCompletionRequestAssistantMessageContent::Text(msg.content)),
+                            ..Default::default()
+                        }
+                    ),
+                    _ => ChatCompletionRequestMessage::User(
+                        ChatCompletionRequestUserMessage {
+                            content: ChatCompletionRequestUserMessageContent::Text(msg.content),
+                            name: None,
+                        }
+                    ),
+                }
+            })
+            .collect();
+
+        let specs: Vec<ChatCompletionTool> = tools
+            .map(|tools| tools.specs())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|spec| ChatCompletionTool {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionObject {
+                    name: spec.name.to_string(),
+                    description: Some(spec.description.to_string()),
+                    parameters: Some(spec.parameters),
+                    strict: None,
+                },
+            })
+            .collect();
+        let mut tool_calls = Vec::new();
+        let mut usage = TokenUsage::default();
+
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            // Create the request
+            let request = CreateChatCompletionRequest {
+                model: self.model.clone(),
+                messages: request_messages.clone(),
+                temperature: Some(self.temperature),
+                top_p: self.top_p,
+                seed: self.seed,
+                tools: (!specs.is_empty()).then(|| specs.clone()),
+                ..Default::default()
+            };
+
+            // Send the request, streamed when a caller listens for the tokens
+            let round = self.completion.create(request, self.stream.as_ref()).await?;
+
+            if let Some(spent) = &round.usage {
+                let spent = TokenUsage::priced(&self.model, spent.prompt_tokens.into(), spent.completion_tokens.into());
+                info!(prompt_tokens = spent.prompt_tokens, completion_tokens = spent.completion_tokens, cost_usd = spent.cost_usd, "LLM usage");
+                if let Some(meter) = &self.meter {
+                    meter(&spent);
+                }
+                usage.add(&spent);
+            }
+
+            let requested = round.tool_calls;
+            let Some(tools) = tools.filter(|_| !requested.is_empty()) else {
+                return Ok(ChatResponse {
+                    input_prompt,
+                    response: round.content.unwrap_or_default(),
+                    tool_calls,
+                    usage,
+                });
+            };
+
+            // Answer every call, then let the model continue with the outputs
+            request_messages.push(ChatCompletionRequestMessage::Assistant(
+                ChatCompletionRequestAssistantMessage {
+                    content: round.content.map(ChatCompletionRequestAssistantMessageContent::Text),
+                    tool_calls: Some(requested.clone()),
+                    ..Default::default()
+                }
+            ));
+            for call in requested {
+                // Arguments that are not valid JSON are passed on as a string, for the tool to refuse
+                let arguments = serde_json::from_str(&call.function.arguments)
+                    .unwrap_or(Value::String(call.function.arguments));
+                let output = tools.call(&call.function.name, &arguments).await;
+                info!(tool = %call.function.name, %arguments, "Tool called");
+
+                request_messages.push(ChatCompletionRequestMessage::Tool(
+                    ChatCompletionRequestToolMessage {
+                        content: ChatCompletionRequestToolMessageContent::Text(output.to_string()),
+                        tool_call_id: call.id,
+                    }
+                ));
+                tool_calls.push(ToolCall { name: call.function.name, arguments, output });
+            }
+        }
+
+        Err(LlmError::ToolLimit(MAX_TOOL_ROUNDS))
+    }
+}
+
+impl OpenAICompletion {
+    async fn round(&self, request: CreateChatCompletionRequest) -> Result<Round, LlmError> {
+        let response = self.client.chat().create(request).await?;
+
+        debug!(?response, "OpenAI response");
+
+        // Extract the response message
+        let message = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(LlmError::EmptyResponse)?
+            .message;
+
+        Ok(Round {
+            content: message.content,
+            tool_calls: message.tool_calls.unwrap_or_default(),
+            usage: response.usage,
+        })
+    }
+
+    // Same as `round` over the streaming API, sending the text to `tokens` as it arrives
+    // and putting the tool calls back together from their fragments
+    async fn stream_round(&self, request: CreateChatCompletionRequest, tokens: &TokenSink) -> Result<Round, LlmError> {
+        let request = CreateChatCompletionRequest {
+            stream_options: Some(ChatCompletionStreamOptions { include_usage: true }),
+            ..request
+        };
+        let mut stream = self.client.chat().create_stream(request).await?;
+
+        let mut answered = false;
+        let mut round = Round { content: None, tool_calls: Vec::new(), usage: None };
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            round.usage = chunk.usage.or(round.usage);
+
+            for choice in chunk.choices.into_iter().filter(|choice| choice.index == 0) {
+                answered = true;
+                // The first chunk of an answer usually carries only its role, with empty text
+                if let Some(text) = choice.delta.content.filter(|text| !text.is_empty()) {
+                    // The caller may have stopped listening; the answer is still completed
+                    let _ = tokens.send(text.clone());
+                    round.content.get_or_insert_with(String::new).push_str(&text);
+                }
+                for fragment in choice.delta.tool_calls.unwrap_or_default() {
+                    let index = fragment.index as usize;
+                    if round.tool_calls.len() <= index {
+                        round.tool_calls.resize_with(index + 1, || ChatCompletionMessageToolCall {
+                            id: String::new(),
+                            r#type: ChatCompletionToolType::Function,
+                            function: FunctionCall { name: String::new(), arguments: String::new() },
+                        });
+                    }
+                    let call = &mut round.tool_calls[index];
+                    if let Some(id) = fragment.id {
+                        call.id = id;
+                    }
+                    if let Some(function) = fragment.function {
+                        call.function.name.push_str(&function.name.unwrap_or_default());
+                        call.function.arguments.push_str(&function.arguments.unwrap_or_default());
+                    }
+                }
+            }
+        }
+
+        if !answered {
+            return Err(LlmError::EmptyResponse);
+        }
+        debug!(content = ?round.content, tool_calls = round.tool_calls.len(), "OpenAI streamed response");
+        Ok(round)
+    }
+}
+
+#[async_trait]
+impl Completion for OpenAICompletion {
+    async fn create(&self, request: CreateChatCompletionRequest, tokens: Option<&TokenSink>) -> Result<Round, LlmError> {
+        match tokens {
+            Some(tokens) => self.stream_round(request, tokens).await,
+            None => self.round(request).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Agent for OpenAIAgent {
+    fn set_prompt(&mut self, prompt: String) -> &mut Self {
+        self.prompt = prompt;
+        self
+    }
+
+    fn stream_to(&mut self, tokens: TokenSink) -> &mut Self {
+        self.stream = Some(tokens);
+        self
+    }
+
+    fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    fn params(&self) -> Value {
+        json!({ "model": self.model, "temperature": self.temperature, "top_p": self.top_p, "seed": self.seed })
+    }
+
+    async fn chat(&self, messages: Vec<Message>) -> Result<ChatResponse, LlmError> {
+        self.complete(messages, None).await
+    }
+
+    async fn chat_with_tools(&self, messages: Vec<Message>, tools: &dyn ToolExecutor) -> Result<ChatResponse, LlmError> {
+        self.complete(messages, Some(tools)).await
+    }
+}
+
+/// Sends `messages` to `agent`, offering `tools` when given.
+pub async fn ask<A: Agent + Sync>(agent: &A, messages: Vec<Message>, tools: Option<&dyn ToolExecutor>) -> Result<ChatResponse, LlmError> {
+    match tools {
+        Some(tools) => agent.chat_with_tools(messages, tools).await,
+        None => agent.chat(messages).await,
+    }
+}
+
+pub struct StableYieldFarmingAgent<A: Agent> {
+    inner: A,
+}
+
+impl<A: Agent> StableYieldFarmingAgent<A> {
+    pub fn new(mut agent: A) -> Self {
+        // Set the specialized finance prompt
+        agent.set_prompt(String::from(
+            "You are a specialized financial advisor focused on stable yield farming strategies. \
+            Provide conservative, well-researched advice on DeFi protocols, yield optimization, \
+            risk assessment, and portfolio diversification. Always prioritize security and \
+            sustainability over high APYs. Include relevant warnings about smart contract risks, \
+            impermanent loss, and market volatility where appropriate.",
+        ));
+
+        Self { inner: agent }
+    }
+
+    // Delegate the chat method to the inner Agent
+    pub async fn chat(&self, messages: Vec<Message>) -> Result<ChatResponse, LlmError> {
+        // Create a new vector with the system prompt as the first message
+        let mut all_messages = vec![Message {
+            role: "system".to_string(),
+            content: self.inner.prompt().to_string(),
+        }];
+
+        // Add the user messages
+        all_messages.extend(messages);
+
+        // Call the inner agent's chat method
+        self.inner.chat(all_messages).await
+    }
+
+    // Same as `chat`, with `tools` offered to the model
+    pub async fn chat_with_tools(&self, messages: Vec<Message>, tools: &dyn ToolExecutor) -> Result<ChatResponse, LlmError> {
+        let mut all_messages = vec![Message {
+            role: "system".to_string(),
+            content: self.inner.prompt().to_string(),
+        }];
+        all_messages.extend(messages);
+
+        self.inner.chat_with_tools(all_messages, tools).await
+    }
+
+    // `prompt` is the `farming_strategy` template rendered with the task's portfolio and prices
+    pub async fn get_farming_strategy(
+        &self,
+        prompt: &RenderedPrompt,
+        tools: &dyn ToolExecutor
+    ) -> Result<ChatResponse, LlmError> {
+        let messages = vec![
+            Message {
+                role: "user".to_string(),
+                content: prompt.text.clone(),
+            },
+        ];
+
+        // Get the AI's recommendation, letting it look up live data through the tools
+        self.chat_with_tools(messages, tools).await
+    }
+}
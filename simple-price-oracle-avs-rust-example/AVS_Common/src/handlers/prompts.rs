@@ -0,0 +1,199 @@
+use alloy_primitives::keccak256;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+use tracing::warn;
+use crate::settings::env_or;
+
+const DEFAULT_PROMPTS_DIR: &str = "prompts";
+
+/// Prompt of `StableYieldFarmingAgent::get_farming_strategy`.
+pub const FARMING_STRATEGY: &str = "farming_strategy";
+
+/// Failure to find or render a prompt template.
+#[derive(Debug, thiserror::Error)]
+pub enum PromptError {
+    #[error("Unknown prompt version {name}/{version}")]
+    UnknownVersion { name: String, version: String },
+    #[error("No value for prompt variable {0}")]
+    MissingVariable(String),
+}
+
+impl PromptError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PromptError::UnknownVersion { .. } => "PROMPT_UNKNOWN_VERSION",
+            PromptError::MissingVariable(_) => "PROMPT_MISSING_VARIABLE",
+        }
+    }
+}
+
+/// A versioned prompt, loaded from `<PROMPTS_DIR>/<name>/<version>.txt`.
+/// Variables are written `{{variable}}`.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: String,
+    pub text: String,
+}
+
+/// A prompt rendered for one task. Its version and hash go in the proof.
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub version: String,
+    pub text: String,
+    pub hash: String, // keccak256 of the rendered text
+}
+
+impl PromptTemplate {
+    /// Substitutes every `{{variable}}`. Values are inserted as is, so
+    /// braces inside a value are never read as another variable.
+    pub fn render(&self, variables: &[(&str, &str)]) -> Result<RenderedPrompt, PromptError> {
+        let mut text = String::with_capacity(self.text.len());
+        let mut rest = self.text.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let Some(length) = rest[start..].find("}}") else {
+                break;
+            };
+            let variable = rest[start + 2..start + length].trim();
+            let value = variables
+                .iter()
+                .find(|(name, _)| *name == variable)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| PromptError::MissingVariable(variable.to_string()))?;
+
+            text.push_str(&rest[..start]);
+            text.push_str(value);
+            rest = &rest[start + length + 2..];
+        }
+        text.push_str(rest);
+
+        Ok(RenderedPrompt {
+            version: self.version.clone(),
+            hash: keccak256(text.as_bytes()).to_string(),
+            text,
+        })
+    }
+}
+
+// Templates by name, then version
+static TEMPLATES: OnceLock<BTreeMap<String, BTreeMap<String, PromptTemplate>>> = OnceLock::new();
+
+// Reads every `<name>/<version>.txt` under `PROMPTS_DIR` on first use
+fn templates() -> &'static BTreeMap<String, BTreeMap<String, PromptTemplate>> {
+    TEMPLATES.get_or_init(|| {
+        let dir = env_or("PROMPTS_DIR", DEFAULT_PROMPTS_DIR.to_string());
+        let mut templates = BTreeMap::new();
+
+        let Ok(names) = fs::read_dir(&dir) else {
+            warn!(%dir, "Prompt directory is missing");
+            return templates;
+        };
+        for name_dir in names.filter_map(Result::ok).filter(|entry| entry.path().is_dir()) {
+            let name = name_dir.file_name().to_string_lossy().to_string();
+            let Ok(files) = fs::read_dir(name_dir.path()) else {
+                continue;
+            };
+
+            let versions: &mut BTreeMap<String, PromptTemplate> = templates.entry(name.clone()).or_default();
+            for file in files.filter_map(Result::ok).map(|entry| entry.path()) {
+                if file.extension().is_none_or(|extension| extension != "txt") {
+                    continue;
+                }
+                let Some(version) = file.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+                    continue;
+                };
+                match fs::read_to_string(&file) {
+                    Ok(text) => {
+                        versions.insert(version.clone(), PromptTemplate { name: name.clone(), version, text });
+                    }
+                    Err(e) => warn!(file = %file.display(), error = %e, "Failed to read prompt template"),
+                }
+            }
+        }
+        templates
+    })
+}
+
+// Orders `v2` before `v10`
+fn version_key(version: &str) -> (u64, &str) {
+    let number = version.trim_start_matches('v').parse().unwrap_or(0);
+    (number, version)
+}
+
+/// Versions of template `name`, oldest first.
+pub fn versions(name: &str) -> Vec<String> {
+    let mut versions: Vec<String> = templates()
+        .get(name)
+        .map(|versions| versions.keys().cloned().collect())
+        .unwrap_or_default();
+    versions.sort_by(|a, b| version_key(a).cmp(&version_key(b)));
+    versions
+}
+
+/// Template `name` at `version`.
+pub fn get(name: &str, version: &str) -> Result<PromptTemplate, PromptError> {
+    templates()
+        .get(name)
+        .and_then(|versions| versions.get(version))
+        .cloned()
+        .ok_or_else(|| PromptError::UnknownVersion { name: name.to_string(), version: version.to_string() })
+}
+
+/// Template `name` at the version pinned by `<NAME>_PROMPT_VERSION`, or its latest version.
+pub fn current(name: &str) -> Result<PromptTemplate, PromptError> {
+    let pinned = env::var(format!("{}_PROMPT_VERSION", name.to_uppercase()))
+        .ok()
+        .filter(|version| !version.trim().is_empty());
+    let version = pinned
+        .or_else(|| versions(name).pop())
+        .ok_or_else(|| PromptError::UnknownVersion { name: name.to_string(), version: "latest".to_string() })?;
+    get(name, &version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(text: &str) -> PromptTemplate {
+        PromptTemplate { name: "test".to_string(), version: "v1".to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn substitutes_every_variable() {
+        let rendered = template("Hold {{portfolio}} at {{ prices }}, again {{portfolio}}.")
+            .render(&[("portfolio", "1 ETH"), ("prices", "ETH: 2500")])
+            .unwrap();
+
+        assert_eq!(rendered.text, "Hold 1 ETH at ETH: 2500, again 1 ETH.");
+        assert_eq!(rendered.version, "v1");
+        assert_eq!(rendered.hash, keccak256(rendered.text.as_bytes()).to_string());
+    }
+
+    #[test]
+    fn fails_on_a_missing_variable() {
+        let error = template("{{portfolio}} and {{prices}}")
+            .render(&[("portfolio", "1 ETH")])
+            .unwrap_err();
+
+        assert!(matches!(error, PromptError::MissingVariable(name) if name == "prices"));
+    }
+
+    #[test]
+    fn inserts_braces_inside_values_as_is() {
+        let rendered = template("{{portfolio}} / {{prices}}")
+            .render(&[("portfolio", "{{prices}}"), ("prices", "{\"ETH\": 2500}}")])
+            .unwrap();
+
+        assert_eq!(rendered.text, "{{prices}} / {\"ETH\": 2500}}");
+    }
+
+    #[test]
+    fn keeps_text_without_variables() {
+        let rendered = template("No variables, a lone {{ brace").render(&[]).unwrap();
+
+        assert_eq!(rendered.text, "No variables, a lone {{ brace");
+    }
+}
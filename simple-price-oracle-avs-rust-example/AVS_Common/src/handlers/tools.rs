@@ -0,0 +1,83 @@
+use serde_json::{json, Value};
+use crate::handlers::openai::ToolSpec;
+use crate::services::oracle_service::{PriceMethod, PriceResponse};
+
+fn pair_parameters(description: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "pair": { "type": "string", "description": description }
+        },
+        "required": ["pair"]
+    })
+}
+
+/// Tools offered to the agent. The performer answers them live and validators
+/// from the recorded transcript, so both must offer the same ones.
+pub fn builtin_specs() -> Vec<ToolSpec> {
+    vec![
+        ToolSpec {
+            name: "get_price",
+            description: "Current spot price of a trading pair on Binance",
+            parameters: pair_parameters("Binance spot pair, such as ETHUSDT"),
+        },
+        ToolSpec {
+            name: "get_portfolio_balances",
+            description: "Balances of the portfolio the strategy is for",
+            parameters: json!({ "type": "object", "properties": {} }),
+        },
+        ToolSpec {
+            name: "get_funding_rate",
+            description: "Latest funding rate of a perpetual futures contract on Binance",
+            parameters: pair_parameters("Binance perpetual pair, such as ETHUSDT"),
+        },
+    ]
+}
+
+/// The `pair` argument of a tool call, trimmed and upper-cased.
+pub fn pair_argument(arguments: &Value) -> Result<String, String> {
+    arguments
+        .get("pair")
+        .and_then(Value::as_str)
+        .map(|pair| pair.trim().to_uppercase())
+        .filter(|pair| !pair.is_empty())
+        .ok_or_else(|| "Missing `pair` argument".to_string())
+}
+
+/// Output of `get_price`: the quote with the method it was derived by, so
+/// validators check it with the same methodology.
+pub fn price_output(quote: &PriceResponse, method: &PriceMethod) -> Value {
+    let mut output = json!({ "symbol": quote.symbol, "price": quote.price, "timestamp": quote.timestamp });
+    if let (Some(output), Value::Object(method)) = (output.as_object_mut(), json!(method)) {
+        output.extend(method);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_pair_argument() {
+        assert_eq!(pair_argument(&json!({ "pair": " ethusdt " })).unwrap(), "ETHUSDT");
+        assert!(pair_argument(&json!({ "pair": "  " })).is_err());
+        assert!(pair_argument(&json!({})).is_err());
+    }
+
+    #[test]
+    fn records_the_price_method_in_the_output() {
+        let quote = PriceResponse { symbol: "ETHUSDT".to_string(), price: "2500.00000000".to_string(), timestamp: Some(1) };
+        let method = PriceMethod::Vwap { trades: 50 };
+
+        let output = price_output(&quote, &method);
+        assert_eq!(
+            output,
+            json!({ "symbol": "ETHUSDT", "price": "2500.00000000", "timestamp": 1, "method": "vwap", "trades": 50 })
+        );
+        assert_eq!(serde_json::from_value::<PriceMethod>(output).unwrap(), method);
+
+        let output = price_output(&quote, &PriceMethod::Last);
+        assert_eq!(serde_json::from_value::<PriceMethod>(output).unwrap(), PriceMethod::Last);
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::HttpResponse;
+use serde_json::json;
+use std::fmt::Write;
+use crate::response::ApiResponse;
+use crate::services::usage::{self, Budgets, TokenUsage, UsageTotals};
+
+// Handler for the `/usage` endpoint, with LLM token usage and cost by day,
+// task definition and model, and the configured budgets
+pub async fn get_usage() -> HttpResponse {
+    ApiResponse::ok(
+        json!({ "totals": usage::snapshot(), "budgets": Budgets::from_env() }),
+        "LLM usage",
+    )
+}
+
+// One Prometheus counter, labelled by model and task definition
+fn write_counter(text: &mut String, name: &str, help: &str, totals: &UsageTotals, value: fn(&TokenUsage) -> f64) {
+    let _ = writeln!(text, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+    for ((model, task_definition_id), usage) in totals.by_model_and_task_definition() {
+        let model = model.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(text, "{}{{model=\"{}\",task_definition_id=\"{}\"}} {}", name, model, task_definition_id, value(usage));
+    }
+}
+
+// Handler for the `/metrics` endpoint, exporting the usage counters in the
+// Prometheus text format
+pub async fn get_metrics() -> HttpResponse {
+    let totals = usage::snapshot();
+    let mut text = String::new();
+    write_counter(&mut text, "llm_prompt_tokens_total", "Prompt tokens sent to the LLM", &totals, |usage| usage.prompt_tokens as f64);
+    write_counter(&mut text, "llm_completion_tokens_total", "Completion tokens returned by the LLM", &totals, |usage| usage.completion_tokens as f64);
+    write_counter(&mut text, "llm_cost_usd_total", "Cost of LLM calls in USD", &totals, |usage| usage.cost_usd);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(text)
+}
@@ -0,0 +1,28 @@
+//! Modules the Execution and Validation services share: price sources and proofs,
+//! task signing, the agent and its LLM middleware, task history and LLM usage.
+pub mod response;
+pub mod settings;
+pub mod telemetry;
+
+pub mod services {
+    pub mod eip712;
+    pub mod jsonl_log;
+    pub mod merkle;
+    pub mod oracle_service;
+    pub mod price_stream;
+    pub mod task_history;
+    pub mod task_meta;
+    pub mod usage;
+}
+
+pub mod handlers {
+    pub mod openai;
+    pub mod agent_middleware;
+    pub mod llm_cache;
+    pub mod prompts;
+    pub mod inputs;
+    pub mod model_policy;
+    pub mod tools;
+    pub mod history;
+    pub mod usage;
+}
@@ -0,0 +1,51 @@
+use actix_web::{http::StatusCode, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Envelope of every JSON response of the Execution and Validation services, which both build it from this crate.
+///
+/// `status` is `success` or `error`. On errors, `error` carries a stable `code`
+/// that attesters and tooling can match on, and `data` any details.
+#[derive(Debug, Serialize)]
+pub struct ApiResponse {
+    pub status: &'static str,
+    pub data: Value,
+    pub message: String,
+    pub error: Option<ErrorBody>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+impl ApiResponse {
+    pub fn success(data: Value, message: &str) -> Self {
+        ApiResponse {
+            status: "success",
+            data,
+            message: message.to_string(),
+            error: None,
+        }
+    }
+
+    pub fn error(code: &str, message: String, data: Value) -> Self {
+        ApiResponse {
+            status: "error",
+            data,
+            message: message.clone(),
+            error: Some(ErrorBody { code: code.to_string(), message }),
+        }
+    }
+
+    /// 200 response with this envelope.
+    pub fn ok(data: Value, message: &str) -> HttpResponse {
+        HttpResponse::Ok().json(Self::success(data, message))
+    }
+
+    /// Response with `status` and this envelope.
+    pub fn with_status(self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status).json(self)
+    }
+}
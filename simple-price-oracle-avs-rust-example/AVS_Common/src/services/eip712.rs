@@ -0,0 +1,244 @@
+use alloy_primitives::{keccak256, Address, Bytes, PrimitiveSignature, B256, U256};
+use alloy_sol_types::{sol, Eip712Domain, SolStruct, SolValue};
+use std::env;
+use std::str::FromStr;
+
+const DEFAULT_DOMAIN_NAME: &str = "SimplePriceOracleAVS";
+const DEFAULT_DOMAIN_VERSION: &str = "1";
+
+sol! {
+    /// Task submission signed by the performer in EIP-712 mode.
+    struct Task {
+        string proofOfTask;
+        bytes data;
+        address taskPerformer;
+        uint16 taskDefinitionId;
+    }
+}
+
+/// Failure to hash, sign or verify a task submission.
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError {
+    #[error("{0}")]
+    Domain(String),
+    #[error("Task definition id {0} does not fit in uint16")]
+    TaskDefinitionId(i32),
+    #[error("Invalid private key: {0}")]
+    InvalidKey(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Could not sign task: {0}")]
+    Sign(String),
+    #[error("Unknown signing mode {0:?}, expected legacy or eip712")]
+    UnknownMode(String),
+}
+
+impl SignerError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignerError::Domain(_) => "SIGNER_DOMAIN_NOT_CONFIGURED",
+            SignerError::TaskDefinitionId(_) => "SIGNER_INVALID_TASK_DEFINITION",
+            SignerError::InvalidKey(_) => "SIGNER_INVALID_KEY",
+            SignerError::InvalidSignature(_) => "SIGNER_INVALID_SIGNATURE",
+            SignerError::Sign(_) => "SIGNER_FAILED",
+            SignerError::UnknownMode(_) => "SIGNER_UNKNOWN_MODE",
+        }
+    }
+}
+
+/// How task submissions are hashed before signing, selected by `SIGNING_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SigningMode {
+    /// keccak256 of `abi.encode(proofOfTask, data, performer, taskDefinitionId)`
+    Legacy,
+    /// EIP-712 hash of a `Task`, bound to a chain and attestation center
+    Eip712,
+}
+
+impl SigningMode {
+    /// Reads the mode from `SIGNING_MODE` (`legacy`, the default, or `eip712`).
+    pub fn from_env() -> Result<Self, SignerError> {
+        match env::var("SIGNING_MODE").unwrap_or_default().trim() {
+            "" | "legacy" => Ok(SigningMode::Legacy),
+            "eip712" => Ok(SigningMode::Eip712),
+            other => Err(SignerError::UnknownMode(other.to_string())),
+        }
+    }
+}
+
+/// Domain from `EIP712_DOMAIN_NAME`, `EIP712_DOMAIN_VERSION`, `CHAIN_ID` and
+/// `ATTESTATION_CENTER_ADDRESS`.
+pub fn domain_from_env() -> Result<Eip712Domain, SignerError> {
+    let name = env::var("EIP712_DOMAIN_NAME").unwrap_or_else(|_| DEFAULT_DOMAIN_NAME.to_string());
+    let version = env::var("EIP712_DOMAIN_VERSION").unwrap_or_else(|_| DEFAULT_DOMAIN_VERSION.to_string());
+    let chain_id = env::var("CHAIN_ID")
+        .map_err(|_| SignerError::Domain("CHAIN_ID is not set in environment variables".to_string()))?
+        .parse::<u64>()
+        .map_err(|_| SignerError::Domain("CHAIN_ID must be a valid number".to_string()))?;
+    let attestation_center = env::var("ATTESTATION_CENTER_ADDRESS")
+        .map_err(|_| SignerError::Domain("ATTESTATION_CENTER_ADDRESS is not set in environment variables".to_string()))?;
+    let attestation_center = Address::from_str(&attestation_center)
+        .map_err(|_| SignerError::Domain("ATTESTATION_CENTER_ADDRESS is not a valid address".to_string()))?;
+
+    Ok(Eip712Domain::new(
+        Some(name.into()),
+        Some(version.into()),
+        Some(U256::from(chain_id)),
+        Some(attestation_center),
+        None,
+    ))
+}
+
+/// Hash the performer signs for a task submission.
+pub fn signing_hash(
+    mode: SigningMode,
+    proof_of_task: &str,
+    data: &Bytes,
+    performer: Address,
+    task_definition_id: i32,
+) -> Result<B256, SignerError> {
+    match mode {
+        SigningMode::Legacy => {
+            let encoded = (proof_of_task.to_string(), data, performer, task_definition_id).abi_encode_params();
+            Ok(keccak256(encoded))
+        }
+        SigningMode::Eip712 => eip712_hash(&domain_from_env()?, proof_of_task, data, performer, task_definition_id),
+    }
+}
+
+/// EIP-712 hash of a `Task` under `domain`.
+pub fn eip712_hash(
+    domain: &Eip712Domain,
+    proof_of_task: &str,
+    data: &Bytes,
+    performer: Address,
+    task_definition_id: i32,
+) -> Result<B256, SignerError> {
+    let task = Task {
+        proofOfTask: proof_of_task.to_string(),
+        data: data.clone(),
+        taskPerformer: performer,
+        taskDefinitionId: u16::try_from(task_definition_id)
+            .map_err(|_| SignerError::TaskDefinitionId(task_definition_id))?,
+    };
+    Ok(task.eip712_signing_hash(domain))
+}
+
+/// Recovers the address that produced a 65-byte hex `signature` over `hash`.
+pub fn recover_signer(hash: B256, signature: &str) -> Result<Address, SignerError> {
+    let signature = PrimitiveSignature::from_str(signature)
+        .map_err(|e| SignerError::InvalidSignature(e.to_string()))?;
+    signature
+        .recover_address_from_prehash(&hash)
+        .map_err(|e| SignerError::InvalidSignature(format!("Could not recover signer: {}", e)))
+}
+
+/// Checks that `signature` over a task submission was made by `performer`.
+pub fn verify_task_signature(
+    mode: SigningMode,
+    proof_of_task: &str,
+    data: &Bytes,
+    performer: Address,
+    task_definition_id: i32,
+    signature: &str,
+) -> Result<bool, SignerError> {
+    let hash = signing_hash(mode, proof_of_task, data, performer, task_definition_id)?;
+    Ok(recover_signer(hash, signature)? == performer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+    use alloy_signer::SignerSync;
+    use alloy_signer_local::PrivateKeySigner;
+    use crate::settings::EnvGuard;
+
+    fn domain(name: &str, chain_id: u64, verifying_contract: Address) -> Eip712Domain {
+        Eip712Domain::new(
+            Some(name.to_string().into()),
+            Some("1".into()),
+            Some(U256::from(chain_id)),
+            Some(verifying_contract),
+            None,
+        )
+    }
+
+    fn sign(signer: &PrivateKeySigner, hash: B256) -> String {
+        signer.sign_hash_sync(&hash).unwrap().to_string()
+    }
+
+    // Expected values were computed with a standalone Python implementation of
+    // keccak256 and the EIP-712 `hashStruct` encoding, which reproduces the
+    // domain separator and digest of the Mail example in the EIP-712 spec.
+    #[test]
+    fn matches_the_eip712_spec_domain_separator() {
+        let mail = domain("Ether Mail", 1, address!("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"));
+        assert_eq!(
+            mail.separator().to_string(),
+            "0xf2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+    }
+
+    #[test]
+    fn matches_a_fixed_task_vector() {
+        let domain = domain(DEFAULT_DOMAIN_NAME, 17000, address!("1111111111111111111111111111111111111111"));
+        assert_eq!(
+            domain.separator().to_string(),
+            "0xa8d2a422af84e066ef158c5bab8fbf015d31ddef0ed4785fbe7030298e8212d0"
+        );
+
+        let hash = eip712_hash(
+            &domain,
+            r#"{"task_id":"t"}"#,
+            &Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]),
+            address!("2222222222222222222222222222222222222222"),
+            7,
+        )
+        .unwrap();
+        assert_eq!(hash.to_string(), "0x657f27052dad663b5bbb5d6634608b0052072bf1093b46a6350103bc2436f296");
+    }
+
+    #[test]
+    fn refuses_task_definition_ids_outside_uint16() {
+        let domain = domain(DEFAULT_DOMAIN_NAME, 1, Address::ZERO);
+        let hash = eip712_hash(&domain, "proof", &Bytes::new(), Address::ZERO, 70_000);
+        assert!(matches!(hash, Err(SignerError::TaskDefinitionId(70_000))));
+    }
+
+    #[test]
+    fn verifies_signatures_in_both_modes() {
+        let signer = PrivateKeySigner::random();
+        let performer = signer.address();
+        let data = Bytes::from_static(b"result");
+
+        // EIP-712 mode reads its domain from the environment
+        let _env = EnvGuard::set(&[
+            ("CHAIN_ID", "17000"),
+            ("ATTESTATION_CENTER_ADDRESS", "0x1111111111111111111111111111111111111111"),
+        ]);
+
+        for mode in [SigningMode::Legacy, SigningMode::Eip712] {
+            let hash = signing_hash(mode, "proof", &data, performer, 1).unwrap();
+            let signature = sign(&signer, hash);
+
+            assert!(verify_task_signature(mode, "proof", &data, performer, 1, &signature).unwrap());
+
+            // Any other proof, result, task definition or performer does not verify
+            assert!(!verify_task_signature(mode, "other proof", &data, performer, 1, &signature).unwrap());
+            assert!(!verify_task_signature(mode, "proof", &Bytes::from_static(b"other"), performer, 1, &signature).unwrap());
+            assert!(!verify_task_signature(mode, "proof", &data, performer, 2, &signature).unwrap());
+            assert!(!verify_task_signature(mode, "proof", &data, Address::ZERO, 1, &signature).unwrap());
+        }
+
+        // A signature in one mode does not verify in the other
+        let legacy = sign(&signer, signing_hash(SigningMode::Legacy, "proof", &data, performer, 1).unwrap());
+        assert!(!verify_task_signature(SigningMode::Eip712, "proof", &data, performer, 1, &legacy).unwrap());
+    }
+
+    #[test]
+    fn refuses_malformed_signatures() {
+        let verified = verify_task_signature(SigningMode::Legacy, "proof", &Bytes::new(), Address::ZERO, 1, "0x1234");
+        assert!(matches!(verified, Err(SignerError::InvalidSignature(_))));
+    }
+}
@@ -0,0 +1,209 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use tracing::warn;
+use crate::settings::env_or;
+
+const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// When a log file is rotated: once it reaches `max_bytes`, it is renamed to
+/// `<path>.1`, older files shift up and all but `max_files` of them are deleted.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotation {
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+impl Rotation {
+    /// Reads `<prefix>_MAX_BYTES` and `<prefix>_MAX_FILES`, such as `TASK_HISTORY_MAX_BYTES`.
+    pub fn from_env(prefix: &str) -> Self {
+        Rotation {
+            max_bytes: env_or(&format!("{}_MAX_BYTES", prefix), DEFAULT_MAX_BYTES),
+            max_files: env_or(&format!("{}_MAX_FILES", prefix), DEFAULT_MAX_FILES),
+        }
+    }
+}
+
+enum Message {
+    Line(String),
+    Flush(Sender<()>),
+}
+
+/// JSON lines file appended to by a background thread, so callers never wait
+/// on the disk. Failing to write is logged, it never fails the caller.
+pub struct JsonlLog {
+    sender: Sender<Message>,
+}
+
+impl JsonlLog {
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> Self {
+        let path = path.into();
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("jsonl-writer".to_string())
+            .spawn(move || write_lines(&path, rotation, receiver))
+            .expect("JSON lines writer thread starts");
+        JsonlLog { sender }
+    }
+
+    /// Queues `value` to be appended as one line.
+    pub fn append<T: Serialize>(&self, value: &T) {
+        match serde_json::to_string(value) {
+            Ok(line) => {
+                let _ = self.sender.send(Message::Line(line));
+            }
+            Err(e) => warn!(error = %e, "Failed to serialize log line"),
+        }
+    }
+
+    /// Waits until every line queued so far is written.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+fn write_lines(path: &Path, rotation: Rotation, receiver: Receiver<Message>) {
+    let mut file: Option<File> = None;
+
+    for message in receiver {
+        let line = match message {
+            Message::Line(line) => line,
+            Message::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+
+        if file.is_none() {
+            file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .inspect_err(|e| warn!(path = %path.display(), error = %e, "Failed to open log file"))
+                .ok();
+        }
+        let Some(open) = file.as_mut() else {
+            continue;
+        };
+        if let Err(e) = writeln!(open, "{}", line) {
+            warn!(path = %path.display(), error = %e, "Failed to append to log file");
+            file = None;
+            continue;
+        }
+
+        let full = open.metadata().is_ok_and(|metadata| metadata.len() >= rotation.max_bytes);
+        if full {
+            file = None;
+            if let Err(e) = rotate(path, rotation.max_files) {
+                warn!(path = %path.display(), error = %e, "Failed to rotate log file");
+            }
+        }
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+// Shifts `<path>.N` to `<path>.N+1`, dropping the oldest, then moves the file to `<path>.1`
+fn rotate(path: &Path, max_files: usize) -> std::io::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(rotated(path, max_files));
+    for index in (1..max_files).rev() {
+        let from = rotated(path, index);
+        if from.exists() {
+            fs::rename(from, rotated(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated(path, 1))
+}
+
+/// Reads the last `keep` records of the log at `path`, oldest first, including
+/// its rotated files. Unreadable lines are skipped.
+pub fn read<T: DeserializeOwned>(path: &Path, rotation: Rotation, keep: usize) -> VecDeque<T> {
+    let mut records = VecDeque::new();
+    for_each(path, rotation, |record| {
+        records.push_back(record);
+        if records.len() > keep {
+            records.pop_front();
+        }
+    });
+    records
+}
+
+/// Passes every record of the log at `path` to `f`, oldest first, including its
+/// rotated files, without holding them all in memory. Unreadable lines are skipped.
+pub fn for_each<T: DeserializeOwned>(path: &Path, rotation: Rotation, mut f: impl FnMut(T)) {
+    let files = (1..=rotation.max_files).rev().map(|index| rotated(path, index)).chain([path.to_path_buf()]);
+
+    for file in files.filter_map(|file| File::open(file).ok()) {
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            match serde_json::from_str(&line) {
+                Ok(record) => f(record),
+                Err(e) => warn!(path = %path.display(), error = %e, "Skipping unreadable log line"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("jsonl-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("log.jsonl")
+    }
+
+    #[test]
+    fn appends_and_reads_back() {
+        let path = temp_path("append");
+        let rotation = Rotation { max_bytes: u64::MAX, max_files: 2 };
+        let log = JsonlLog::open(&path, rotation);
+        for n in 0..5 {
+            log.append(&n);
+        }
+        log.flush();
+
+        let all: VecDeque<u32> = read(&path, rotation, 10);
+        assert_eq!(all, [0, 1, 2, 3, 4]);
+        let last: VecDeque<u32> = read(&path, rotation, 2);
+        assert_eq!(last, [3, 4]);
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_max_files() {
+        let path = temp_path("rotate");
+        // Every line is "N\n", so each file holds two lines
+        let rotation = Rotation { max_bytes: 4, max_files: 2 };
+        let log = JsonlLog::open(&path, rotation);
+        for n in 0..7 {
+            log.append(&n);
+        }
+        log.flush();
+
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "2\n3\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "4\n5\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "6\n");
+        assert!(!rotated(&path, 3).exists());
+
+        let kept: VecDeque<u32> = read(&path, rotation, 10);
+        assert_eq!(kept, [2, 3, 4, 5, 6]);
+    }
+}
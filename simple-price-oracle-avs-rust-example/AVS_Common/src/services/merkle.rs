@@ -0,0 +1,219 @@
+use alloy_primitives::{keccak256, B256, U256};
+use alloy_sol_types::SolValue;
+use serde::Serialize;
+use crate::services::oracle_service::PriceProof;
+
+/// Fixed-point decimals of the prices committed to in a Merkle leaf.
+pub const PRICE_DECIMALS: u8 = 8;
+
+/// One `(pair, price, decimals, timestamp)` entry of a batch price result.
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceLeaf {
+    pub pair: String,
+    pub price: String, // scaled integer, as a decimal string
+    pub decimals: u8,
+    pub timestamp: u64,
+}
+
+impl PriceLeaf {
+    pub fn from_proof(proof: &PriceProof) -> Result<Self, String> {
+        Ok(PriceLeaf {
+            pair: proof.symbol.clone(),
+            price: scale_price(&proof.price, PRICE_DECIMALS)?.to_string(),
+            decimals: PRICE_DECIMALS,
+            timestamp: proof.timestamp,
+        })
+    }
+
+    /// Leaf hash as computed by OpenZeppelin's `StandardMerkleTree`:
+    /// `keccak256(bytes.concat(keccak256(abi.encode(pair, price, decimals, timestamp))))`
+    pub fn hash(&self) -> Result<B256, String> {
+        let price = U256::from_str_radix(&self.price, 10).map_err(|_| format!("Invalid scaled price {}", self.price))?;
+        // uint8 is padded to a full word by abi.encode, so it encodes exactly like a U256
+        let decimals = U256::from(self.decimals);
+        let encoded = (self.pair.clone(), price, decimals, self.timestamp).abi_encode_params();
+        Ok(keccak256(keccak256(encoded)))
+    }
+}
+
+/// Converts a decimal price string such as `"3456.78"` into an integer scaled by `10^decimals`.
+pub fn scale_price(price: &str, decimals: u8) -> Result<U256, String> {
+    let (whole, fraction) = price.split_once('.').unwrap_or((price, ""));
+    let decimals = decimals as usize;
+
+    // Digits beyond the supported precision must be zero, nothing is rounded away
+    if fraction.len() > decimals && fraction[decimals..].chars().any(|c| c != '0') {
+        return Err(format!("Price {} has more than {} decimals", price, decimals));
+    }
+    let fraction = &fraction[..fraction.len().min(decimals)];
+
+    let digits = format!("{}{:0<width$}", whole, fraction, width = decimals);
+    U256::from_str_radix(&digits, 10).map_err(|_| format!("Invalid price value {}", price))
+}
+
+/// Merkle tree with sorted-pair hashing, compatible with OpenZeppelin's `MerkleProof.verify`.
+///
+/// Leaves are sorted and an odd node is promoted to the next layer, whereas OpenZeppelin's
+/// `StandardMerkleTree` lays leaves out as a complete binary tree. Roots therefore only match
+/// theirs for power-of-two leaf counts; what is guaranteed is that every root and proof built
+/// here verifies with `MerkleProof.verify`.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    layers: Vec<Vec<B256>>,
+}
+
+impl MerkleTree {
+    pub fn new(mut leaves: Vec<B256>) -> Self {
+        leaves.sort();
+
+        let mut layers = vec![leaves];
+        while layers.last().is_some_and(|layer| layer.len() > 1) {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(*left, *right),
+                    // An odd node is promoted to the next layer unchanged
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+
+        Self { layers }
+    }
+
+    pub fn root(&self) -> B256 {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sibling hashes from `leaf` up to the root, or None if the leaf is not in the tree.
+    pub fn proof(&self, leaf: B256) -> Option<Vec<B256>> {
+        let mut index = self.layers.first()?.iter().position(|node| *node == leaf)?;
+        let mut proof = Vec::new();
+
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < layer.len() {
+                proof.push(layer[sibling]);
+            }
+            index /= 2;
+        }
+
+        Some(proof)
+    }
+}
+
+/// Commutative keccak256 of two nodes, as in OpenZeppelin's `MerkleProof._hashPair`.
+pub fn hash_pair(a: B256, b: B256) -> B256 {
+    if a < b {
+        keccak256([a.as_slice(), b.as_slice()].concat())
+    } else {
+        keccak256([b.as_slice(), a.as_slice()].concat())
+    }
+}
+
+/// Rust equivalent of OpenZeppelin's `MerkleProof.verify(proof, root, leaf)`.
+pub fn verify(proof: &[B256], root: B256, leaf: B256) -> bool {
+    proof.iter().fold(leaf, |computed, node| hash_pair(computed, *node)) == root
+}
+
+/// Builds the Merkle tree over every price in a batch proof.
+pub fn build_price_tree(prices: &[PriceProof]) -> Result<(Vec<PriceLeaf>, MerkleTree), String> {
+    let leaves = prices
+        .iter()
+        .map(PriceLeaf::from_proof)
+        .collect::<Result<Vec<PriceLeaf>, String>>()?;
+    let hashes = leaves.iter().map(PriceLeaf::hash).collect::<Result<Vec<B256>, String>>()?;
+    Ok((leaves, MerkleTree::new(hashes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::oracle_service::PriceMethod;
+    use alloy_primitives::{address, b256};
+
+    fn price_proof(symbol: &str, price: &str) -> PriceProof {
+        PriceProof {
+            symbol: symbol.to_string(),
+            price: price.to_string(),
+            timestamp: 1_735_689_600,
+            method: PriceMethod::Last,
+        }
+    }
+
+    // The example of OpenZeppelin's merkle-tree README:
+    // StandardMerkleTree.of([[0x1111..., 5e18], [0x2222..., 2.5e18]], ["address", "uint256"])
+    #[test]
+    fn matches_openzeppelin_standard_merkle_tree() {
+        let leaf = |account, amount: u128| keccak256(keccak256((account, U256::from(amount)).abi_encode_params()));
+        let leaves = vec![
+            leaf(address!("1111111111111111111111111111111111111111"), 5_000_000_000_000_000_000),
+            leaf(address!("2222222222222222222222222222222222222222"), 2_500_000_000_000_000_000),
+        ];
+
+        let tree = MerkleTree::new(leaves.clone());
+        assert_eq!(tree.root(), b256!("d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"));
+        for leaf in leaves {
+            assert!(verify(&tree.proof(leaf).unwrap(), tree.root(), leaf));
+        }
+    }
+
+    // Leaf hashes, root and proofs computed with a standalone Python keccak256 and ABI encoding
+    // script, which also reproduces the root of the OpenZeppelin example above
+    #[test]
+    fn price_tree_matches_fixed_vector() {
+        let prices = vec![
+            price_proof("ETHUSDT", "3456.78"),
+            price_proof("BTCUSDT", "96123.45"),
+            price_proof("SOLUSDT", "189.5"),
+        ];
+        let (leaves, tree) = build_price_tree(&prices).unwrap();
+
+        let hashes: Vec<B256> = leaves.iter().map(|leaf| leaf.hash().unwrap()).collect();
+        assert_eq!(
+            hashes,
+            vec![
+                b256!("3f5fb654329ef0f5c95089a472723ffec2d2095931702247b468c329ab2b2d2b"),
+                b256!("0ab6684c3525b47d189629939163b741aaff328f19c55c01dd8adc19335fa043"),
+                b256!("3366e538f487eed84a30db79e8e8535a85809e8ac9286df5a41b009ee2beb776"),
+            ]
+        );
+        assert_eq!(tree.root(), b256!("35c7e5c6d7892d55bc101c2980f22dcaba9f57f5b6abb45736e8ee0d1c91a1f9"));
+
+        // The odd leaf is promoted, so its proof is the single sibling subtree
+        assert_eq!(
+            tree.proof(hashes[0]).unwrap(),
+            vec![b256!("9b483839bf3ff39f4e442a714635a9780fbf28629d26afa4d3e28577e26921c8")]
+        );
+        assert_eq!(tree.proof(hashes[1]).unwrap(), vec![hashes[2], hashes[0]]);
+        for hash in &hashes {
+            assert!(verify(&tree.proof(*hash).unwrap(), tree.root(), *hash));
+        }
+    }
+
+    #[test]
+    fn tampered_leaf_does_not_verify() {
+        let (leaves, tree) = build_price_tree(&[price_proof("ETHUSDT", "3456.78"), price_proof("BTCUSDT", "96123.45")]).unwrap();
+        let proof = tree.proof(leaves[0].hash().unwrap()).unwrap();
+
+        let tampered = PriceLeaf::from_proof(&price_proof("ETHUSDT", "3456.79")).unwrap();
+        assert!(!verify(&proof, tree.root(), tampered.hash().unwrap()));
+    }
+
+    #[test]
+    fn invalid_prices_are_errors() {
+        assert!(build_price_tree(&[price_proof("ETHUSDT", "abc")]).is_err());
+        assert!(build_price_tree(&[price_proof("ETHUSDT", "1.123456789")]).is_err());
+
+        let leaf = PriceLeaf { pair: "ETHUSDT".to_string(), price: "-1".to_string(), decimals: 8, timestamp: 0 };
+        assert!(leaf.hash().is_err());
+    }
+}
@@ -0,0 +1,475 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::num::ParseFloatError;
+use std::ops::RangeInclusive;
+use crate::services::price_stream;
+use crate::services::task_meta::TaskMeta;
+use crate::settings::env_or;
+
+const BINANCE_API_URL: &str = "https://api.binance.com/api/v3";
+const BINANCE_FUTURES_API_URL: &str = "https://fapi.binance.com/fapi/v1";
+const DEFAULT_VWAP_TRADES: u32 = 100;
+// Binance serves at most 1000 recent trades
+const VWAP_TRADES_RANGE: RangeInclusive<u32> = 1..=1000;
+const DEFAULT_DEPTH_NOTIONAL: f64 = 10_000.0;
+const DEPTH_LIMIT: u32 = 500;
+const DEFAULT_TASK_PAIRS: &str = "ETHUSDT";
+const QUOTE_ASSETS: [&str; 6] = ["USDT", "USDC", "FDUSD", "BTC", "ETH", "EUR"];
+
+/// Independent venues used to corroborate Binance prices.
+pub const SECONDARY_SOURCES: [&str; 2] = ["bybit", "okx"];
+
+/// Failure to obtain a price from a market data source.
+#[derive(Debug, thiserror::Error)]
+pub enum OracleError {
+    #[error("Price source request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Unknown price source {0}")]
+    UnknownSource(String),
+    #[error("Unknown price method {0}, expected last, vwap or depth_mid")]
+    UnknownMethod(String),
+    #[error("Invalid price method parameter: {0}")]
+    InvalidMethodParameter(String),
+    #[error("Unsupported pair {0}")]
+    UnsupportedPair(String),
+    #[error("Invalid price data: {0}")]
+    InvalidData(String),
+    #[error("Not enough price history for {0}")]
+    InsufficientHistory(String),
+}
+
+impl OracleError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OracleError::Request(_) => "ORACLE_UNAVAILABLE",
+            OracleError::UnknownSource(_) => "ORACLE_UNKNOWN_SOURCE",
+            OracleError::UnknownMethod(_) => "ORACLE_UNKNOWN_METHOD",
+            OracleError::InvalidMethodParameter(_) => "ORACLE_INVALID_METHOD_PARAMETER",
+            OracleError::UnsupportedPair(_) => "ORACLE_UNSUPPORTED_PAIR",
+            OracleError::InvalidData(_) => "ORACLE_INVALID_DATA",
+            OracleError::InsufficientHistory(_) => "ORACLE_INSUFFICIENT_HISTORY",
+        }
+    }
+}
+
+impl From<ParseFloatError> for OracleError {
+    fn from(e: ParseFloatError) -> Self {
+        OracleError::InvalidData(e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PriceResponse {
+    pub symbol: String,
+    pub price: String,
+    #[serde(default)]
+    pub timestamp: Option<u64>, // time of the underlying trade in milliseconds, when known
+}
+
+/// How a price is derived from the market. It is recorded in the proof so
+/// validators recompute the price with the same methodology.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum PriceMethod {
+    /// Last traded price
+    Last,
+    /// Volume-weighted average over the most recent `trades` trades
+    Vwap { trades: u32 },
+    /// Mid of the average fill prices for `notional` quote on each side of the book
+    DepthMid { notional: f64 },
+}
+
+impl PriceMethod {
+    /// Reads the method from `PRICE_METHOD` (`last`, the default, `vwap` or `depth_mid`),
+    /// with `PRICE_VWAP_TRADES` (1 to 1000) and `PRICE_DEPTH_NOTIONAL` as parameters.
+    pub fn from_env() -> Result<Self, OracleError> {
+        let method = match env::var("PRICE_METHOD").unwrap_or_default().trim() {
+            "" | "last" => PriceMethod::Last,
+            "vwap" => {
+                let trades = env_or("PRICE_VWAP_TRADES", DEFAULT_VWAP_TRADES);
+                if !VWAP_TRADES_RANGE.contains(&trades) {
+                    return Err(OracleError::InvalidMethodParameter(format!(
+                        "PRICE_VWAP_TRADES is {}, expected {} to {}",
+                        trades,
+                        VWAP_TRADES_RANGE.start(),
+                        VWAP_TRADES_RANGE.end()
+                    )));
+                }
+                PriceMethod::Vwap { trades }
+            }
+            "depth_mid" => PriceMethod::DepthMid {
+                notional: env_or("PRICE_DEPTH_NOTIONAL", DEFAULT_DEPTH_NOTIONAL),
+            },
+            other => return Err(OracleError::UnknownMethod(other.to_string())),
+        };
+        Ok(method)
+    }
+}
+
+/// Proof of a price task: the price together with the methodology used.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriceProof {
+    pub symbol: String,
+    pub price: String,
+    #[serde(default)]
+    pub timestamp: u64, // unix seconds at which the price was observed
+    #[serde(flatten)]
+    pub method: PriceMethod,
+}
+
+/// Proof of a batch price task: one entry per pair, signed as a single result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriceBatchProof {
+    #[serde(flatten)]
+    pub meta: Option<TaskMeta>,
+    pub prices: Vec<PriceProof>,
+}
+
+/// Pairs reported by a task definition, read from `TASK_PAIRS_<id>` and
+/// falling back to `TASK_PAIRS` (both comma separated).
+pub fn task_pairs(task_definition_id: i32) -> Vec<String> {
+    let pairs = env::var(format!("TASK_PAIRS_{}", task_definition_id))
+        .or_else(|_| env::var("TASK_PAIRS"))
+        .unwrap_or_else(|_| DEFAULT_TASK_PAIRS.to_string());
+    parse_pairs(&pairs)
+}
+
+/// Pairs reported by any task definition: those in `TASK_PAIRS` (or its default when
+/// unset) followed by those in every `TASK_PAIRS_<id>`, without duplicates.
+pub fn configured_pairs() -> Vec<String> {
+    configured_pairs_from(env::vars())
+}
+
+fn configured_pairs_from(vars: impl Iterator<Item = (String, String)>) -> Vec<String> {
+    let mut default = DEFAULT_TASK_PAIRS.to_string();
+    let mut overrides: Vec<(String, String)> = Vec::new();
+    for (name, value) in vars {
+        if name == "TASK_PAIRS" {
+            default = value;
+        } else if name.starts_with("TASK_PAIRS_") {
+            overrides.push((name, value));
+        }
+    }
+    overrides.sort();
+
+    let mut pairs = parse_pairs(&default);
+    for pair in overrides.iter().flat_map(|(_, value)| parse_pairs(value)) {
+        if !pairs.contains(&pair) {
+            pairs.push(pair);
+        }
+    }
+    pairs
+}
+
+/// Comma separated pairs, trimmed and upper-cased.
+pub fn parse_pairs(pairs: &str) -> Vec<String> {
+    pairs
+        .split(',')
+        .map(|pair| pair.trim().to_uppercase())
+        .filter(|pair| !pair.is_empty())
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Trade {
+    qty: String,
+    quote_qty: String,
+    time: u64,
+}
+
+#[derive(Deserialize)]
+struct OrderBook {
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
+}
+
+pub async fn get_price(pair: &str) -> Result<PriceResponse, OracleError> {
+    // Serve from the streaming cache while its entry is fresh
+    if let Some(cached) = price_stream::latest_price(pair) {
+        return Ok(PriceResponse {
+            symbol: pair.to_uppercase(),
+            price: cached.price,
+            timestamp: Some(cached.trade_time),
+        });
+    }
+
+    let url = format!("{}/ticker/price?symbol={}", BINANCE_API_URL, pair);
+
+    // Send the GET request and await the response
+    let response = reqwest::get(&url).await?;
+
+    // Deserialize the JSON response into the PriceResponse struct
+    let price_response: PriceResponse = response.json().await?;
+
+    Ok(price_response)
+}
+
+/// Time of the most recent trade of `pair` on Binance, in unix milliseconds.
+pub async fn get_last_trade_time(pair: &str) -> Result<u64, OracleError> {
+    let url = format!("{}/trades?symbol={}&limit=1", BINANCE_API_URL, pair);
+    let trades: Vec<Trade> = reqwest::get(&url).await?.json().await?;
+
+    trades
+        .first()
+        .map(|trade| trade.time)
+        .ok_or_else(|| OracleError::InvalidData(format!("No recent trade for {}", pair)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PremiumIndex {
+    symbol: String,
+    last_funding_rate: String,
+    next_funding_time: u64,
+}
+
+/// Funding rate of a perpetual futures contract.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FundingRate {
+    pub symbol: String,
+    pub funding_rate: f64,
+    pub next_funding_time: u64, // unix milliseconds
+}
+
+/// Latest funding rate of the `pair` perpetual on Binance USDⓈ-M futures.
+pub async fn get_funding_rate(pair: &str) -> Result<FundingRate, OracleError> {
+    let url = format!("{}/premiumIndex?symbol={}", BINANCE_FUTURES_API_URL, pair.to_uppercase());
+    let index: PremiumIndex = reqwest::get(&url).await?.json().await?;
+
+    Ok(FundingRate {
+        symbol: index.symbol,
+        funding_rate: index.last_funding_rate.parse()?,
+        next_funding_time: index.next_funding_time,
+    })
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize)]
+struct SymbolInfo {
+    status: String,
+}
+
+/// Trading status of `pair` on Binance, such as `TRADING`, `HALT` or `BREAK`.
+pub async fn get_market_status(pair: &str) -> Result<String, OracleError> {
+    let url = format!("{}/exchangeInfo?symbol={}", BINANCE_API_URL, pair);
+    let info: ExchangeInfo = reqwest::get(&url).await?.json().await?;
+
+    Ok(info
+        .symbols
+        .into_iter()
+        .next()
+        .map(|symbol| symbol.status)
+        .unwrap_or_default())
+}
+
+/// Fetches the price of `pair` using the given methodology.
+#[tracing::instrument(name = "oracle_fetch", skip(method), fields(method = ?method))]
+pub async fn get_price_by(pair: &str, method: &PriceMethod) -> Result<PriceResponse, OracleError> {
+    let (price, timestamp) = match method {
+        PriceMethod::Last => return get_price(pair).await,
+        PriceMethod::Vwap { trades } => get_vwap(pair, *trades).await?,
+        // The order book is a live snapshot, it has no trade time
+        PriceMethod::DepthMid { notional } => (get_depth_mid(pair, *notional).await?, None),
+    };
+
+    Ok(PriceResponse {
+        symbol: pair.to_uppercase(),
+        price: format!("{:.8}", price),
+        timestamp,
+    })
+}
+
+// Returns the VWAP and the time of the most recent trade
+async fn get_vwap(pair: &str, trades: u32) -> Result<(f64, Option<u64>), OracleError> {
+    let url = format!("{}/trades?symbol={}&limit={}", BINANCE_API_URL, pair, trades);
+    let recent_trades: Vec<Trade> = reqwest::get(&url).await?.json().await?;
+    vwap(pair, &recent_trades)
+}
+
+fn vwap(pair: &str, trades: &[Trade]) -> Result<(f64, Option<u64>), OracleError> {
+    let mut base_volume = 0.0;
+    let mut quote_volume = 0.0;
+    for trade in trades {
+        base_volume += trade.qty.parse::<f64>()?;
+        quote_volume += trade.quote_qty.parse::<f64>()?;
+    }
+
+    if base_volume <= 0.0 {
+        return Err(OracleError::InvalidData(format!("No recent trade volume for {}", pair)));
+    }
+
+    let last_trade_time = trades.iter().map(|trade| trade.time).max();
+    Ok((quote_volume / base_volume, last_trade_time))
+}
+
+async fn get_depth_mid(pair: &str, notional: f64) -> Result<f64, OracleError> {
+    let url = format!("{}/depth?symbol={}&limit={}", BINANCE_API_URL, pair, DEPTH_LIMIT);
+    let book: OrderBook = reqwest::get(&url).await?.json().await?;
+
+    let bid = average_fill_price(&book.bids, notional)
+        .ok_or_else(|| OracleError::InvalidData(format!("Not enough bid depth for {} at notional {}", pair, notional)))?;
+    let ask = average_fill_price(&book.asks, notional)
+        .ok_or_else(|| OracleError::InvalidData(format!("Not enough ask depth for {} at notional {}", pair, notional)))?;
+
+    Ok((bid + ask) / 2.0)
+}
+
+// Walks one side of the book until `notional` quote is filled and returns the
+// average fill price, or None if the side is too thin. Levels without a positive
+// price and quantity are skipped.
+fn average_fill_price(levels: &[(String, String)], notional: f64) -> Option<f64> {
+    if notional <= 0.0 {
+        return None;
+    }
+
+    let mut remaining = notional;
+    let mut filled_base = 0.0;
+
+    for (price, qty) in levels {
+        let price: f64 = price.parse().ok()?;
+        let qty: f64 = qty.parse().ok()?;
+        if price <= 0.0 || qty <= 0.0 {
+            continue;
+        }
+        let take = remaining.min(price * qty);
+
+        filled_base += take / price;
+        remaining -= take;
+        if remaining <= 0.0 {
+            return Some(notional / filled_base);
+        }
+    }
+
+    None
+}
+
+#[derive(Deserialize)]
+struct BybitResponse {
+    result: BybitResult,
+}
+
+#[derive(Deserialize)]
+struct BybitResult {
+    list: Vec<BybitTicker>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BybitTicker {
+    last_price: String,
+}
+
+#[derive(Deserialize)]
+struct OkxResponse {
+    data: Vec<OkxTicker>,
+}
+
+#[derive(Deserialize)]
+struct OkxTicker {
+    last: String,
+}
+
+/// Fetches the last price of `pair` from one of the `SECONDARY_SOURCES`.
+#[tracing::instrument(name = "oracle_fetch_secondary")]
+pub async fn get_secondary_price(source: &str, pair: &str) -> Result<f64, OracleError> {
+    let pair = pair.to_uppercase();
+    let price = match source {
+        "bybit" => {
+            let url = format!("https://api.bybit.com/v5/market/tickers?category=spot&symbol={}", pair);
+            let response: BybitResponse = reqwest::get(&url).await?.json().await?;
+            response.result.list.into_iter().next().map(|ticker| ticker.last_price)
+        }
+        "okx" => {
+            // OKX instruments are written BASE-QUOTE
+            let quote = QUOTE_ASSETS
+                .iter()
+                .find(|quote| pair.ends_with(*quote) && pair.len() > quote.len())
+                .ok_or_else(|| OracleError::UnsupportedPair(pair.clone()))?;
+            let inst_id = format!("{}-{}", &pair[..pair.len() - quote.len()], quote);
+            let url = format!("https://www.okx.com/api/v5/market/ticker?instId={}", inst_id);
+            let response: OkxResponse = reqwest::get(&url).await?.json().await?;
+            response.data.into_iter().next().map(|ticker| ticker.last)
+        }
+        _ => return Err(OracleError::UnknownSource(source.to_string())),
+    };
+
+    let price = price.ok_or_else(|| OracleError::InvalidData(format!("{} has no price for {}", source, pair)))?;
+    Ok(price.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::EnvGuard;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn collects_the_pairs_of_every_task_definition() {
+        assert_eq!(configured_pairs_from(vars(&[("PATH", "/bin")])), vec!["ETHUSDT"]);
+        assert_eq!(
+            configured_pairs_from(vars(&[
+                ("TASK_PAIRS_7", "solusdt, ethusdt"),
+                ("TASK_PAIRS", "ETHUSDT,BTCUSDT"),
+                ("TASK_PAIRS_2", "BNBUSDT,"),
+            ])),
+            vec!["ETHUSDT", "BTCUSDT", "BNBUSDT", "SOLUSDT"]
+        );
+    }
+
+    #[test]
+    fn checks_the_vwap_trade_count() {
+        for (trades, valid) in [("0", false), ("1", true), ("1000", true), ("1001", false)] {
+            let _env = EnvGuard::set(&[("PRICE_METHOD", "vwap"), ("PRICE_VWAP_TRADES", trades)]);
+            match PriceMethod::from_env() {
+                Ok(method) => assert!(valid && method == PriceMethod::Vwap { trades: trades.parse().unwrap() }),
+                Err(e) => assert!(!valid && e.code() == "ORACLE_INVALID_METHOD_PARAMETER", "{}", trades),
+            }
+        }
+        let _env = EnvGuard::set(&[("PRICE_METHOD", "vwap"), ("PRICE_VWAP_TRADES", "")]);
+        assert_eq!(PriceMethod::from_env().unwrap(), PriceMethod::Vwap { trades: DEFAULT_VWAP_TRADES });
+    }
+
+    fn trade(qty: &str, quote_qty: &str, time: u64) -> Trade {
+        Trade { qty: qty.to_string(), quote_qty: quote_qty.to_string(), time }
+    }
+
+    #[test]
+    fn weights_the_vwap_by_volume() {
+        // 1 at 100 and 3 at 200: 700 quote over 4 base
+        let (price, last_trade_time) = vwap("ETHUSDT", &[trade("1", "100", 20), trade("3", "600", 10)]).unwrap();
+        assert_eq!(price, 175.0);
+        assert_eq!(last_trade_time, Some(20));
+
+        assert_eq!(vwap("ETHUSDT", &[]).unwrap_err().code(), "ORACLE_INVALID_DATA");
+        assert_eq!(vwap("ETHUSDT", &[trade("x", "1", 0)]).unwrap_err().code(), "ORACLE_INVALID_DATA");
+    }
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<(String, String)> {
+        levels.iter().map(|(price, qty)| (price.to_string(), qty.to_string())).collect()
+    }
+
+    #[test]
+    fn walks_the_book_for_the_average_fill_price() {
+        // Exactly fills the first level
+        assert_eq!(average_fill_price(&levels(&[("100", "10"), ("200", "10")]), 1_000.0), Some(100.0));
+        // 1000 at 100 and 1000 at 200: 2000 quote for 15 base
+        let price = average_fill_price(&levels(&[("100", "10"), ("200", "10")]), 2_000.0).unwrap();
+        assert!((price - 2_000.0 / 15.0).abs() < 1e-9);
+        // Levels without a positive price or quantity are skipped
+        assert_eq!(average_fill_price(&levels(&[("0", "10"), ("100", "0"), ("100", "10")]), 500.0), Some(100.0));
+        // Not enough depth
+        assert_eq!(average_fill_price(&levels(&[("100", "1"), ("200", "1")]), 1_000.0), None);
+        assert_eq!(average_fill_price(&levels(&[("100", "10")]), 0.0), None);
+    }
+}
@@ -0,0 +1,176 @@
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info, warn};
+use crate::services::oracle_service;
+use crate::settings::env_or;
+
+const BINANCE_STREAM_URL: &str = "wss://stream.binance.com:9443/stream";
+const DEFAULT_MAX_AGE_MS: u64 = 5_000;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct CachedPrice {
+    pub price: String,
+    pub trade_time: u64, // Binance trade time in milliseconds
+    pub received_at: Instant,
+}
+
+// Combined stream payloads are wrapped as {"stream": "...", "data": {...}}
+#[derive(Deserialize)]
+struct StreamEnvelope {
+    data: TradeEvent,
+}
+
+#[derive(Deserialize)]
+struct TradeEvent {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "T")]
+    trade_time: u64,
+}
+
+// Latest trade price per symbol, shared by every request handler
+static PRICE_CACHE: OnceLock<RwLock<HashMap<String, CachedPrice>>> = OnceLock::new();
+
+fn cache() -> &'static RwLock<HashMap<String, CachedPrice>> {
+    PRICE_CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Symbols to subscribe to, read from `PRICE_STREAM_SYMBOLS` (comma separated) and
+/// defaulting to the pairs of every task definition (`TASK_PAIRS`, `TASK_PAIRS_<id>`).
+/// An empty value disables streaming and every lookup goes to REST.
+pub fn stream_symbols() -> Vec<String> {
+    match env::var("PRICE_STREAM_SYMBOLS") {
+        Ok(symbols) => oracle_service::parse_pairs(&symbols),
+        Err(_) => oracle_service::configured_pairs(),
+    }
+}
+
+/// Maximum age of a cached price before callers fall back to REST.
+pub fn max_age() -> Duration {
+    Duration::from_millis(env_or("PRICE_CACHE_MAX_AGE_MS", DEFAULT_MAX_AGE_MS))
+}
+
+/// Returns the cached price for `pair` if one was received within `max_age`.
+pub fn latest_price(pair: &str) -> Option<CachedPrice> {
+    let cached = cache().read().ok()?.get(&pair.to_uppercase()).cloned()?;
+    Some(cached).filter(|cached| cached.received_at.elapsed() <= max_age())
+}
+
+/// Starts the background task that keeps the price cache up to date.
+///
+/// The task subscribes to the Binance `@trade` stream of every symbol and
+/// reconnects with exponential backoff whenever the socket drops.
+pub fn spawn(symbols: Vec<String>) -> Option<JoinHandle<()>> {
+    if symbols.is_empty() {
+        info!("Price stream disabled, using REST for every price lookup.");
+        return None;
+    }
+
+    Some(tokio::spawn(run(symbols)))
+}
+
+async fn run(symbols: Vec<String>) {
+    let streams = symbols
+        .iter()
+        .map(|symbol| format!("{}@trade", symbol.to_lowercase()))
+        .collect::<Vec<String>>()
+        .join("/");
+    let url = format!("{}?streams={}", BINANCE_STREAM_URL, streams);
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        match connect_async(url.as_str()).await {
+            Ok((mut socket, _)) => {
+                info!(%streams, "Price stream connected");
+                backoff = INITIAL_BACKOFF;
+
+                while let Some(message) = socket.next().await {
+                    match message {
+                        Ok(Message::Text(text)) => handle_message(&text),
+                        Ok(Message::Close(frame)) => {
+                            warn!(?frame, "Price stream closed by server");
+                            break;
+                        }
+                        // Pings are answered by tungstenite itself
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(error = %e, "Price stream error");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => error!(error = %e, "Error connecting to price stream"),
+        }
+
+        info!(?backoff, "Reconnecting price stream");
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn handle_message(text: &str) {
+    let event = match serde_json::from_str::<StreamEnvelope>(text) {
+        Ok(envelope) => envelope.data,
+        Err(e) => {
+            warn!(error = %e, "Ignoring unexpected price stream message");
+            return;
+        }
+    };
+
+    if let Ok(mut cache) = cache().write() {
+        cache.insert(
+            event.symbol,
+            CachedPrice {
+                price: event.price,
+                trade_time: event.trade_time,
+                received_at: Instant::now(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_trades_from_combined_stream_messages() {
+        handle_message(
+            r#"{"stream":"streamausdt@trade","data":{"e":"trade","E":1700000000001,"s":"STREAMAUSDT","t":12345,"p":"2500.10","q":"0.5","T":1700000000000,"m":true,"M":true}}"#,
+        );
+        let cached = latest_price("streamausdt").unwrap();
+        assert_eq!(cached.price, "2500.10");
+        assert_eq!(cached.trade_time, 1_700_000_000_000);
+
+        // A raw trade event without the combined stream envelope is ignored
+        handle_message(r#"{"e":"trade","s":"STREAMBUSDT","p":"1.0","T":1700000000000}"#);
+        assert!(latest_price("STREAMBUSDT").is_none());
+    }
+
+    #[test]
+    fn expires_cached_prices_after_the_max_age() {
+        let stale = Instant::now().checked_sub(max_age() + Duration::from_secs(1)).unwrap();
+        cache().write().unwrap().insert(
+            "STREAMCUSDT".to_string(),
+            CachedPrice { price: "1.0".to_string(), trade_time: 0, received_at: stale },
+        );
+        assert!(latest_price("STREAMCUSDT").is_none());
+
+        cache().write().unwrap().insert(
+            "STREAMCUSDT".to_string(),
+            CachedPrice { price: "1.0".to_string(), trade_time: 0, received_at: Instant::now() },
+        );
+        assert!(latest_price("STREAMCUSDT").is_some());
+    }
+}
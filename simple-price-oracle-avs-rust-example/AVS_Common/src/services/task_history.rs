@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::env;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::response::ErrorBody;
+use crate::services::jsonl_log::{self, JsonlLog, Rotation};
+use crate::services::usage::TokenUsage;
+
+const DEFAULT_TASK_HISTORY_PATH: &str = "task_history.jsonl";
+// Records kept in memory and served by queries
+const MAX_RECORDS: usize = 10_000;
+
+const CSV_HEADER: [&str; 16] = [
+    "recorded_at", "task_id", "kind", "task_definition_id", "outcome", "performer", "inputs",
+    "proof_of_task", "result", "signature", "error_code", "error_message", "details",
+    "prompt_tokens", "completion_tokens", "cost_usd",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    Price,
+    Agent,
+}
+
+/// What became of a task: its submission by the performer, or the vote of a validator.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskOutcome {
+    /// Accepted by the aggregator
+    Submitted,
+    /// Signed, but not accepted by the aggregator
+    Failed,
+    /// Approved by this validator
+    Approved,
+    /// Rejected by this validator
+    Rejected,
+    /// Refused by this performer before it ran, such as for malformed inputs
+    Refused,
+}
+
+/// Everything known about a task once it was submitted or voted on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub task_id: Option<String>,
+    pub kind: TaskKind,
+    pub task_definition_id: i32,
+    pub performer: Option<String>,
+    pub inputs: Value,
+    pub proof_of_task: String,
+    pub result: Option<String>, // 0x-prefixed result bytes
+    pub signature: Option<String>,
+    pub outcome: TaskOutcome,
+    pub error: Option<ErrorBody>,
+    pub details: Value, // validation details, or anything else worth keeping
+    #[serde(default)]
+    pub usage: Option<TokenUsage>, // LLM usage of agent tasks
+    pub recorded_at: u64, // unix seconds
+}
+
+impl TaskRecord {
+    pub fn new(kind: TaskKind, task_definition_id: i32, proof_of_task: String, outcome: TaskOutcome) -> Self {
+        TaskRecord {
+            task_id: None,
+            kind,
+            task_definition_id,
+            performer: None,
+            inputs: Value::Null,
+            proof_of_task,
+            result: None,
+            signature: None,
+            outcome,
+            error: None,
+            details: Value::Null,
+            usage: None,
+            recorded_at: now_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Jsonl,
+}
+
+/// Filters of `GET /tasks`; every filter is optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskQuery {
+    pub from: Option<u64>, // unix seconds, inclusive
+    pub to: Option<u64>,   // unix seconds, inclusive
+    pub task_definition_id: Option<i32>,
+    pub outcome: Option<TaskOutcome>,
+    pub performer: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+impl TaskQuery {
+    fn matches(&self, record: &TaskRecord) -> bool {
+        self.from.is_none_or(|from| record.recorded_at >= from)
+            && self.to.is_none_or(|to| record.recorded_at <= to)
+            && self.task_definition_id.is_none_or(|id| record.task_definition_id == id)
+            && self.outcome.is_none_or(|outcome| record.outcome == outcome)
+            && self.performer.as_ref().is_none_or(|performer| {
+                record.performer.as_ref().is_some_and(|p| p.eq_ignore_ascii_case(performer))
+            })
+    }
+}
+
+static RECORDS: OnceLock<RwLock<VecDeque<TaskRecord>>> = OnceLock::new();
+static LOG: OnceLock<Option<JsonlLog>> = OnceLock::new();
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+// File the history is appended to, from `TASK_HISTORY_PATH`; an empty value keeps it in memory only
+fn history_path() -> Option<String> {
+    match env::var("TASK_HISTORY_PATH") {
+        Ok(path) if path.trim().is_empty() => None,
+        Ok(path) => Some(path),
+        Err(_) => Some(DEFAULT_TASK_HISTORY_PATH.to_string()),
+    }
+}
+
+// Loads the most recent records from the history file and its rotations on first use
+fn records() -> &'static RwLock<VecDeque<TaskRecord>> {
+    RECORDS.get_or_init(|| {
+        let records = history_path()
+            .map(|path| jsonl_log::read(Path::new(&path), Rotation::from_env("TASK_HISTORY"), MAX_RECORDS))
+            .unwrap_or_default();
+        RwLock::new(records)
+    })
+}
+
+// Writer of the history file, rotated by `TASK_HISTORY_MAX_BYTES` and `TASK_HISTORY_MAX_FILES`
+fn log() -> Option<&'static JsonlLog> {
+    LOG.get_or_init(|| history_path().map(|path| JsonlLog::open(path, Rotation::from_env("TASK_HISTORY"))))
+        .as_ref()
+}
+
+/// Keeps `record` and queues it to be appended to the history file. Failing to
+/// write the file is logged, it never fails the task.
+pub fn record(record: TaskRecord) {
+    // Load the file before appending to it, so the new record is not read back twice
+    let records = records();
+    if let Some(log) = log() {
+        log.append(&record);
+    }
+
+    let mut records = records.write().unwrap();
+    records.push_back(record);
+    if records.len() > MAX_RECORDS {
+        records.pop_front();
+    }
+}
+
+/// Records matching `query`, newest first.
+pub fn query(query: &TaskQuery) -> Vec<TaskRecord> {
+    select(&records().read().unwrap(), query)
+}
+
+fn select(records: &VecDeque<TaskRecord>, query: &TaskQuery) -> Vec<TaskRecord> {
+    records
+        .iter()
+        .rev()
+        .filter(|record| query.matches(record))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
+
+/// One JSON record per line.
+pub fn to_jsonl(records: &[TaskRecord]) -> String {
+    records
+        .iter()
+        .filter_map(|record| serde_json::to_string(record).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// CSV with a header row; `inputs` and `details` are written as JSON.
+pub fn to_csv(records: &[TaskRecord]) -> String {
+    let mut csv = CSV_HEADER.join(",") + "\n";
+    for record in records {
+        let (error_code, error_message) = record
+            .error
+            .as_ref()
+            .map(|error| (error.code.clone(), error.message.clone()))
+            .unwrap_or_default();
+        let usage = record.usage.clone().unwrap_or_default();
+        let row = [
+            record.recorded_at.to_string(),
+            record.task_id.clone().unwrap_or_default(),
+            json_text(&record.kind),
+            record.task_definition_id.to_string(),
+            json_text(&record.outcome),
+            record.performer.clone().unwrap_or_default(),
+            record.inputs.to_string(),
+            record.proof_of_task.clone(),
+            record.result.clone().unwrap_or_default(),
+            record.signature.clone().unwrap_or_default(),
+            error_code,
+            error_message,
+            record.details.to_string(),
+            usage.prompt_tokens.to_string(),
+            usage.completion_tokens.to_string(),
+            usage.cost_usd.to_string(),
+        ];
+        csv += &row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+    }
+    csv
+}
+
+// Serialized name of a unit enum variant, without the JSON quotes
+fn json_text<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+// Quotes a field containing a separator, quote or line break, doubling inner quotes
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(task_id: &str, recorded_at: u64, outcome: TaskOutcome, performer: Option<&str>) -> TaskRecord {
+        let mut record = TaskRecord::new(TaskKind::Price, 1, "{}".to_string(), outcome);
+        record.task_id = Some(task_id.to_string());
+        record.performer = performer.map(str::to_string);
+        record.recorded_at = recorded_at;
+        record
+    }
+
+    fn ids(records: Vec<TaskRecord>) -> Vec<String> {
+        records.into_iter().filter_map(|record| record.task_id).collect()
+    }
+
+    #[test]
+    fn filters_records_newest_first() {
+        let records: VecDeque<TaskRecord> = [
+            record("a", 100, TaskOutcome::Submitted, Some("0xAbC")),
+            record("b", 200, TaskOutcome::Failed, None),
+            record("c", 300, TaskOutcome::Submitted, Some("0xdef")),
+        ]
+        .into();
+
+        assert_eq!(ids(select(&records, &TaskQuery::default())), ["c", "b", "a"]);
+        assert_eq!(ids(select(&records, &TaskQuery { from: Some(200), ..Default::default() })), ["c", "b"]);
+        assert_eq!(ids(select(&records, &TaskQuery { to: Some(200), ..Default::default() })), ["b", "a"]);
+        assert_eq!(
+            ids(select(&records, &TaskQuery { outcome: Some(TaskOutcome::Submitted), ..Default::default() })),
+            ["c", "a"]
+        );
+        // Performers match regardless of case, and records without one never match
+        assert_eq!(
+            ids(select(&records, &TaskQuery { performer: Some("0xabc".to_string()), ..Default::default() })),
+            ["a"]
+        );
+        assert_eq!(ids(select(&records, &TaskQuery { limit: Some(2), ..Default::default() })), ["c", "b"]);
+        assert!(select(&records, &TaskQuery { task_definition_id: Some(2), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn escapes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+        let mut record = record("t,1", 100, TaskOutcome::Rejected, None);
+        record.details = serde_json::json!({ "reason": "bad" });
+        let csv = to_csv(&[record]);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("100,\"t,1\",price,1,rejected,,null,{},"), "{}", row);
+        assert!(row.contains(",\"{\"\"reason\"\":\"\"bad\"\"}\","), "{}", row);
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::settings::env_or;
+
+const DEFAULT_TASK_TTL_SECS: u64 = 300;
+
+// Seeded with the start time so nonces keep increasing across restarts
+static NEXT_NONCE: AtomicU64 = AtomicU64::new(0);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Identity of a task, carried inside its proof so it cannot be submitted twice or replayed late.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskMeta {
+    pub task_id: String,
+    pub nonce: u64,
+    pub expires_at: u64, // unix seconds
+}
+
+impl TaskMeta {
+    /// New task with a random id, the next nonce and an expiry `TASK_TTL_SECS` from now.
+    pub fn new() -> Self {
+        let ttl = env_or("TASK_TTL_SECS", DEFAULT_TASK_TTL_SECS);
+
+        let _ = NEXT_NONCE.compare_exchange(0, now_secs() * 1_000, Ordering::SeqCst, Ordering::SeqCst);
+
+        TaskMeta {
+            task_id: uuid::Uuid::new_v4().to_string(),
+            nonce: NEXT_NONCE.fetch_add(1, Ordering::SeqCst),
+            expires_at: now_secs() + ttl,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now_secs() > self.expires_at
+    }
+
+    /// Key under which the task is remembered as submitted or seen.
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.task_id, self.nonce)
+    }
+}
+
+impl Default for TaskMeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a task was refused as a replay.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReplayReason {
+    MissingTaskMetadata,
+    ExpiredTask,
+    DuplicateTask,
+    DuplicateProof,
+}
+
+impl ReplayReason {
+    /// Stable code, as serialized.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ReplayReason::MissingTaskMetadata => "MISSING_TASK_METADATA",
+            ReplayReason::ExpiredTask => "EXPIRED_TASK",
+            ReplayReason::DuplicateTask => "DUPLICATE_TASK",
+            ReplayReason::DuplicateProof => "DUPLICATE_PROOF",
+        }
+    }
+}
+
+/// Bounded set of recently seen keys; the oldest key is forgotten once it is full.
+pub struct SeenSet {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenSet {
+    pub fn new(capacity: usize) -> Self {
+        SeenSet {
+            keys: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Records `key`, returning false if it was already present.
+    pub fn insert(&mut self, key: String) -> bool {
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_set_forgets_the_oldest_key_when_full() {
+        let mut seen = SeenSet::new(2);
+        assert!(seen.insert("a".to_string()));
+        assert!(seen.insert("b".to_string()));
+        assert!(!seen.insert("a".to_string()));
+        assert_eq!(seen.len(), 2);
+
+        assert!(seen.insert("c".to_string()));
+        assert_eq!(seen.len(), 2);
+        assert!(!seen.contains("a"));
+        assert!(seen.contains("b"));
+        assert!(seen.contains("c"));
+
+        // A forgotten key can be inserted again
+        assert!(seen.insert("a".to_string()));
+        assert!(!seen.contains("b"));
+    }
+
+    #[test]
+    fn new_tasks_get_increasing_nonces_and_distinct_keys() {
+        let first = TaskMeta::new();
+        let second = TaskMeta::new();
+        assert!(second.nonce > first.nonce);
+        assert_ne!(first.key(), second.key());
+        assert!(!first.is_expired());
+
+        let expired = TaskMeta { expires_at: now_secs() - 1, ..first };
+        assert!(expired.is_expired());
+    }
+}
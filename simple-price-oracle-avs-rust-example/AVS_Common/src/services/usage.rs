@@ -0,0 +1,336 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+use tracing::warn;
+use crate::services::jsonl_log::{self, JsonlLog, Rotation};
+use crate::settings::env_opt;
+
+const DEFAULT_LLM_USAGE_PATH: &str = "llm_usage.jsonl";
+
+// USD per million prompt and completion tokens, overridden or extended by `LLM_PRICES`
+const DEFAULT_PRICES: [(&str, f64, f64); 4] = [
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+];
+
+/// Work refused because LLM spending reached a budget.
+#[derive(Debug, thiserror::Error)]
+pub enum BudgetError {
+    #[error("{scope} LLM budget of ${budget:.2} is spent (${spent:.4} today)")]
+    Exceeded { scope: String, budget: f64, spent: f64 },
+}
+
+impl BudgetError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            BudgetError::Exceeded { .. } => "LLM_BUDGET_EXCEEDED",
+        }
+    }
+}
+
+/// Tokens used by LLM calls and what they cost.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl TokenUsage {
+    /// Usage of one call to `model`, priced from the price table.
+    pub fn priced(model: &str, prompt_tokens: u64, completion_tokens: u64) -> Self {
+        let cost_usd = match price(model) {
+            Some(price) => {
+                (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion) / 1_000_000.0
+            }
+            None => {
+                warn!(%model, "No price for model, its usage is counted at no cost");
+                0.0
+            }
+        };
+        TokenUsage { prompt_tokens, completion_tokens, cost_usd }
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// USD per million tokens of one model.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+static PRICES: OnceLock<BTreeMap<String, ModelPrice>> = OnceLock::new();
+
+fn prices() -> &'static BTreeMap<String, ModelPrice> {
+    PRICES.get_or_init(|| price_table(env::var("LLM_PRICES").ok().as_deref()))
+}
+
+// Built-in prices, then those of `LLM_PRICES`, a JSON object such as
+// `{"gpt-4o": {"prompt": 2.5, "completion": 10}}`
+fn price_table(configured: Option<&str>) -> BTreeMap<String, ModelPrice> {
+    let mut prices: BTreeMap<String, ModelPrice> = DEFAULT_PRICES
+        .iter()
+        .map(|(model, prompt, completion)| (model.to_string(), ModelPrice { prompt: *prompt, completion: *completion }))
+        .collect();
+    if let Some(text) = configured.filter(|text| !text.trim().is_empty()) {
+        match serde_json::from_str::<BTreeMap<String, ModelPrice>>(text) {
+            Ok(configured) => prices.extend(configured),
+            Err(e) => warn!(error = %e, "Ignoring unreadable LLM_PRICES"),
+        }
+    }
+    prices
+}
+
+/// Price of `model`, or of the longest priced name it starts with, so dated
+/// snapshots such as `gpt-4o-2024-08-06` use the price of `gpt-4o`.
+pub fn price(model: &str) -> Option<ModelPrice> {
+    price_in(prices(), model)
+}
+
+fn price_in(prices: &BTreeMap<String, ModelPrice>, model: &str) -> Option<ModelPrice> {
+    prices
+        .iter()
+        .filter(|(name, _)| model.starts_with(name.as_str()))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| *price)
+}
+
+/// Usage of one model round of a task, as kept in the usage log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub task_id: Option<String>,
+    pub task_definition_id: i32,
+    pub model: String,
+    pub usage: TokenUsage,
+    pub recorded_at: i64, // unix seconds
+}
+
+/// Usage totals since the usage log was started.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    pub by_day: BTreeMap<String, TokenUsage>, // UTC day, such as 2025-03-01
+    pub by_task_definition: BTreeMap<i32, TokenUsage>,
+    pub by_model: BTreeMap<String, TokenUsage>,
+    #[serde(skip)]
+    by_day_and_task_definition: BTreeMap<(String, i32), TokenUsage>,
+    #[serde(skip)]
+    by_model_and_task_definition: BTreeMap<(String, i32), TokenUsage>,
+}
+
+impl UsageTotals {
+    fn add(&mut self, entry: &UsageEntry) {
+        let day = day_of(entry.recorded_at);
+        let id = entry.task_definition_id;
+        self.by_day.entry(day.clone()).or_default().add(&entry.usage);
+        self.by_task_definition.entry(id).or_default().add(&entry.usage);
+        self.by_model.entry(entry.model.clone()).or_default().add(&entry.usage);
+        self.by_day_and_task_definition.entry((day, id)).or_default().add(&entry.usage);
+        self.by_model_and_task_definition.entry((entry.model.clone(), id)).or_default().add(&entry.usage);
+    }
+
+    /// Usage by model and task definition, as exported by `/metrics`.
+    pub fn by_model_and_task_definition(&self) -> &BTreeMap<(String, i32), TokenUsage> {
+        &self.by_model_and_task_definition
+    }
+}
+
+static TOTALS: OnceLock<RwLock<UsageTotals>> = OnceLock::new();
+
+fn day_of(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn today() -> String {
+    day_of(chrono::Utc::now().timestamp())
+}
+
+// File usage is appended to, from `LLM_USAGE_PATH`; an empty value keeps it in memory only
+fn usage_path() -> Option<String> {
+    match env::var("LLM_USAGE_PATH") {
+        Ok(path) if path.trim().is_empty() => None,
+        Ok(path) => Some(path),
+        Err(_) => Some(DEFAULT_LLM_USAGE_PATH.to_string()),
+    }
+}
+
+// Sums the usage log, rotated files included, on first use, so totals and budgets survive a restart
+fn totals() -> &'static RwLock<UsageTotals> {
+    TOTALS.get_or_init(|| {
+        let mut totals = UsageTotals::default();
+        if let Some(path) = usage_path() {
+            jsonl_log::for_each(Path::new(&path), Rotation::from_env("LLM_USAGE"), |entry| totals.add(&entry));
+        }
+        RwLock::new(totals)
+    })
+}
+
+static LOG: OnceLock<Option<JsonlLog>> = OnceLock::new();
+
+// Usage log appended to by a background writer, rotated by `LLM_USAGE_MAX_BYTES` and `LLM_USAGE_MAX_FILES`
+fn log() -> Option<&'static JsonlLog> {
+    LOG.get_or_init(|| usage_path().map(|path| JsonlLog::open(path, Rotation::from_env("LLM_USAGE"))))
+        .as_ref()
+}
+
+/// Adds usage spent for a task to the totals and queues it for the usage log.
+/// Failing to write the log is logged, it never fails the task.
+pub fn record(task_id: Option<String>, task_definition_id: i32, model: &str, usage: &TokenUsage) {
+    let entry = UsageEntry {
+        task_id,
+        task_definition_id,
+        model: model.to_string(),
+        usage: usage.clone(),
+        recorded_at: chrono::Utc::now().timestamp(),
+    };
+    // Load the log before appending to it, so the entry is not counted twice
+    let mut totals = totals().write().unwrap();
+    if let Some(log) = log() {
+        log.append(&entry);
+    }
+    totals.add(&entry);
+}
+
+/// Called with the usage of every model round as soon as it is spent.
+pub type UsageSink = Arc<dyn Fn(&TokenUsage) + Send + Sync>;
+
+/// Sink recording each round of one task on its own, so the rounds before a
+/// failed one are counted too.
+pub fn meter(task_id: Option<String>, task_definition_id: i32, model: &str) -> UsageSink {
+    let model = model.to_string();
+    Arc::new(move |usage| record(task_id.clone(), task_definition_id, &model, usage))
+}
+
+/// Current usage totals.
+pub fn snapshot() -> UsageTotals {
+    totals().read().unwrap().clone()
+}
+
+/// Daily budgets in USD, from `LLM_DAILY_BUDGET_USD` and
+/// `LLM_TASK_DEFINITION_DAILY_BUDGET_USD`; unset or 0 means no budget.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Budgets {
+    pub daily: Option<f64>,
+    pub task_definition_daily: Option<f64>,
+}
+
+impl Budgets {
+    pub fn from_env() -> Self {
+        let budget = |name: &str| env_opt(name).filter(|budget: &f64| *budget > 0.0);
+        Budgets {
+            daily: budget("LLM_DAILY_BUDGET_USD"),
+            task_definition_daily: budget("LLM_TASK_DEFINITION_DAILY_BUDGET_USD"),
+        }
+    }
+}
+
+/// Refuses new work for `task_definition_id` once today's spending, overall or
+/// for that task definition, reached its budget.
+pub fn check_budget(task_definition_id: i32) -> Result<(), BudgetError> {
+    check_budget_in(&totals().read().unwrap(), Budgets::from_env(), &today(), task_definition_id)
+}
+
+fn check_budget_in(totals: &UsageTotals, budgets: Budgets, today: &str, task_definition_id: i32) -> Result<(), BudgetError> {
+    if let Some(budget) = budgets.daily {
+        let spent = totals.by_day.get(today).map(|usage| usage.cost_usd).unwrap_or_default();
+        if spent >= budget {
+            return Err(BudgetError::Exceeded { scope: "Daily".to_string(), budget, spent });
+        }
+    }
+    if let Some(budget) = budgets.task_definition_daily {
+        let spent = totals
+            .by_day_and_task_definition
+            .get(&(today.to_string(), task_definition_id))
+            .map(|usage| usage.cost_usd)
+            .unwrap_or_default();
+        if spent >= budget {
+            return Err(BudgetError::Exceeded { scope: format!("Task definition {} daily", task_definition_id), budget, spent });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 1_740_787_200; // 2025-03-01T00:00:00Z
+
+    fn entry(task_definition_id: i32, model: &str, cost_usd: f64, recorded_at: i64) -> UsageEntry {
+        UsageEntry {
+            task_id: None,
+            task_definition_id,
+            model: model.to_string(),
+            usage: TokenUsage { prompt_tokens: 100, completion_tokens: 10, cost_usd },
+            recorded_at,
+        }
+    }
+
+    #[test]
+    fn prices_models_by_their_longest_prefix() {
+        let prices = price_table(None);
+        assert_eq!(price_in(&prices, "gpt-4o-2024-08-06").unwrap().prompt, 2.50);
+        assert_eq!(price_in(&prices, "gpt-4o-mini-2024-07-18").unwrap().prompt, 0.15);
+        assert_eq!(price_in(&prices, "gpt-4").map(|price| price.prompt), None);
+        assert!(price_in(&prices, "claude").is_none());
+    }
+
+    #[test]
+    fn overrides_and_extends_prices_from_llm_prices() {
+        let prices = price_table(Some(r#"{"gpt-4o": {"prompt": 1, "completion": 2}, "o1": {"prompt": 15, "completion": 60}}"#));
+        assert_eq!(price_in(&prices, "gpt-4o-2024-08-06").unwrap().completion, 2.0);
+        assert_eq!(price_in(&prices, "gpt-4o-mini").unwrap().prompt, 0.15);
+        assert_eq!(price_in(&prices, "o1-preview").unwrap().prompt, 15.0);
+
+        // An unreadable value keeps the built-in prices
+        let prices = price_table(Some("not json"));
+        assert_eq!(price_in(&prices, "gpt-4o").unwrap().prompt, 2.50);
+        assert!(price_in(&prices, "o1").is_none());
+    }
+
+    #[test]
+    fn totals_usage_by_day_and_task_definition() {
+        let mut totals = UsageTotals::default();
+        totals.add(&entry(1, "gpt-4o", 0.5, DAY));
+        totals.add(&entry(2, "gpt-4o-mini", 0.25, DAY + 3_600));
+        totals.add(&entry(1, "gpt-4o", 1.0, DAY + 86_400));
+
+        assert_eq!(totals.by_day["2025-03-01"].cost_usd, 0.75);
+        assert_eq!(totals.by_day["2025-03-02"].cost_usd, 1.0);
+        assert_eq!(totals.by_task_definition[&1].cost_usd, 1.5);
+        assert_eq!(totals.by_task_definition[&1].prompt_tokens, 200);
+        assert_eq!(totals.by_model["gpt-4o-mini"].completion_tokens, 10);
+        assert_eq!(totals.by_model_and_task_definition()[&("gpt-4o".to_string(), 1)].cost_usd, 1.5);
+    }
+
+    #[test]
+    fn refuses_work_once_a_budget_is_spent() {
+        let mut totals = UsageTotals::default();
+        totals.add(&entry(1, "gpt-4o", 0.5, DAY));
+        totals.add(&entry(2, "gpt-4o", 0.25, DAY));
+
+        assert!(check_budget_in(&totals, Budgets::default(), "2025-03-01", 1).is_ok());
+
+        let daily = Budgets { daily: Some(0.75), task_definition_daily: None };
+        let e = check_budget_in(&totals, daily, "2025-03-01", 3).unwrap_err();
+        assert_eq!(e.code(), "LLM_BUDGET_EXCEEDED");
+        // Spending on other days does not count
+        assert!(check_budget_in(&totals, daily, "2025-03-02", 3).is_ok());
+
+        let per_task_definition = Budgets { daily: None, task_definition_daily: Some(0.5) };
+        assert!(check_budget_in(&totals, per_task_definition, "2025-03-01", 1).is_err());
+        assert!(check_budget_in(&totals, per_task_definition, "2025-03-01", 2).is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+//! Settings read from the service environment.
+use std::env;
+use std::str::FromStr;
+
+/// Value of the environment variable `name`, or `default` when it is unset or does not parse.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
+
+/// Value of the environment variable `name`, or `None` when it is unset or does not parse.
+pub fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| value.trim().parse().ok())
+}
+
+/// Environment variables set for one test, restored to their previous values on drop.
+///
+/// The environment is shared by every test of a process, so each guard holds a process-wide
+/// lock: tests that set variables run one at a time and never see each other's values.
+#[cfg(any(test, feature = "test-util"))]
+pub struct EnvGuard {
+    previous: Vec<(String, Option<String>)>,
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl EnvGuard {
+    pub fn set(vars: &[(&str, &str)]) -> Self {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        // A test that panicked while holding the lock has already restored its variables
+        let lock = LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        let previous = vars
+            .iter()
+            .map(|(name, value)| {
+                let previous = env::var(name).ok();
+                env::set_var(name, value);
+                (name.to_string(), previous)
+            })
+            .collect();
+        EnvGuard { previous, _lock: lock }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (name, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(value) => env::set_var(&name, value),
+                None => env::remove_var(&name),
+            }
+        }
+    }
+}
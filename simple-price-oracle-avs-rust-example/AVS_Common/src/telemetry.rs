@@ -0,0 +1,93 @@
+use std::env;
+use tracing::Span;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Installs the global subscriber.
+///
+/// Logs are JSON lines unless `LOG_FORMAT=text`, filtered by `RUST_LOG` (default `info`).
+/// Built with the `otlp` feature, spans are also exported to `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// when it is set.
+pub fn init(service_name: &str) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = env::var("LOG_FORMAT").map_or(true, |format| format != "text");
+
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(json.then(|| fmt::layer().json().with_current_span(true).with_span_list(true)))
+        .with((!json).then(fmt::layer));
+
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp::tracer(service_name).map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+
+    registry.init();
+    tracing::info!(service = service_name, json, "Telemetry initialized");
+}
+
+/// Flushes spans still buffered for the OTLP exporter.
+pub fn shutdown() {
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Span covering all work done for one task, keyed by the task id carried in its proof.
+///
+/// With OTLP export the span's trace id is the task id itself, so the execution and
+/// validation sides of a task land in the same trace without passing any headers.
+pub fn task_span(task_id: &str) -> Span {
+    let span = tracing::info_span!("task", task_id = %task_id);
+
+    #[cfg(feature = "otlp")]
+    otlp::join_task_trace(&span, task_id);
+
+    span
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider as _};
+    use opentelemetry::{Context, KeyValue};
+    use opentelemetry_sdk::{runtime, trace::{Tracer, TracerProvider}, Resource};
+    use std::env;
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    pub fn tracer(service_name: &str) -> Option<Tracer> {
+        env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|endpoint| !endpoint.is_empty())?;
+
+        // The subscriber is not installed yet, so errors go to stderr
+        let exporter = match opentelemetry_otlp::SpanExporter::builder().with_tonic().build() {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("Could not create OTLP exporter, spans will not be exported: {}", e);
+                return None;
+            }
+        };
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name.to_string())]))
+            .build();
+        let tracer = provider.tracer(service_name.to_string());
+        opentelemetry::global::set_tracer_provider(provider);
+
+        Some(tracer)
+    }
+
+    // Parents the span on a remote context whose trace id is the task's UUID
+    pub fn join_task_trace(span: &Span, task_id: &str) {
+        let Ok(uuid) = uuid::Uuid::parse_str(task_id) else {
+            return;
+        };
+        let bytes = *uuid.as_bytes();
+        let mut span_id = [0u8; 8];
+        span_id.copy_from_slice(&bytes[8..]);
+
+        let parent = SpanContext::new(
+            TraceId::from_bytes(bytes),
+            SpanId::from_bytes(span_id),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        span.set_parent(Context::new().with_remote_span_context(parent));
+    }
+}
@@ -0,0 +1,116 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::{json, Value};
+use tracing::error;
+use crate::handlers::openai::LlmError;
+use crate::handlers::inputs::InputError;
+use crate::handlers::model_policy::PolicyError;
+use crate::handlers::prompts::PromptError;
+use crate::response::ApiResponse;
+use crate::services::dal_service::{RpcError, SignedTask};
+use crate::services::eip712::SignerError;
+use crate::services::jobs::JobError;
+use crate::services::oracle_service::OracleError;
+use crate::services::price_guard::Suppression;
+use crate::services::task_meta::ReplayReason;
+use crate::services::usage::BudgetError;
+
+/// Every error a handler can return, each with a stable code and HTTP status.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Oracle(#[from] OracleError),
+    #[error(transparent)]
+    Signer(#[from] SignerError),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    /// The task was signed but the aggregator did not take it.
+    #[error("{source}")]
+    NotSubmitted { source: RpcError, signed_task: Box<SignedTask> },
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+    #[error(transparent)]
+    Prompt(#[from] PromptError),
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+    #[error(transparent)]
+    Input(#[from] InputError),
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
+    Job(#[from] JobError),
+    #[error("Task refused: {}", .0.code())]
+    Replay(ReplayReason),
+    #[error("{} price(s) suppressed before submission", .0.len())]
+    Suppressed(Vec<Suppression>),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Oracle(e) => e.code(),
+            ApiError::Signer(e) => e.code(),
+            ApiError::Rpc(e) => e.code(),
+            ApiError::NotSubmitted { source, .. } => source.code(),
+            ApiError::Llm(e) => e.code(),
+            ApiError::Prompt(e) => e.code(),
+            ApiError::Policy(e) => e.code(),
+            ApiError::Input(e) => e.code(),
+            ApiError::Budget(e) => e.code(),
+            ApiError::Job(e) => e.code(),
+            ApiError::Replay(reason) => reason.code(),
+            ApiError::Suppressed(_) => "PRICE_SUPPRESSED",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// Logs the error with its code, in the current span.
+    pub fn log(&self) {
+        error!(code = self.code(), error = %self, "Request failed");
+    }
+
+    fn data(&self) -> Value {
+        match self {
+            ApiError::Suppressed(suppressions) => json!({ "suppressions": suppressions }),
+            ApiError::NotSubmitted { signed_task, .. } => json!({ "signed_task": signed_task }),
+            _ => json!({}),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Oracle(OracleError::UnknownSource(_) | OracleError::UnsupportedPair(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Oracle(OracleError::UnknownMethod(_) | OracleError::InvalidMethodParameter(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Oracle(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Signer(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Rpc(_) | ApiError::NotSubmitted { .. } => StatusCode::BAD_GATEWAY,
+            ApiError::Llm(LlmError::NotConfigured) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Llm(LlmError::Timeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Llm(LlmError::CircuitOpen(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Llm(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Prompt(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Policy(PolicyError::NotAllowed { .. }) => StatusCode::BAD_REQUEST,
+            ApiError::Policy(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Input(_) => StatusCode::BAD_REQUEST,
+            ApiError::Budget(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Job(JobError::NotFound(_)) => StatusCode::NOT_FOUND,
+            ApiError::Job(JobError::QueueFull) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Job(JobError::NotCancellable(_)) => StatusCode::CONFLICT,
+            ApiError::Job(JobError::WebhookNotAllowed(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Replay(_) => StatusCode::CONFLICT,
+            ApiError::Suppressed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiResponse::error(self.code(), self.to_string(), self.data()).with_status(self.status_code())
+    }
+}
@@ -0,0 +1,21 @@
+use actix_web::{http::StatusCode, HttpResponse, Responder};
+use serde_json::json;
+use crate::response::ApiResponse;
+use crate::services::doctor;
+
+// Handler for the `/health` liveness endpoint; answers as long as the server runs
+pub async fn health_check() -> impl Responder {
+    "Server is running"
+}
+
+// Handler for the `/ready` endpoint, reporting the status of each dependency
+pub async fn ready() -> HttpResponse {
+    let report = doctor::readiness().await;
+
+    if report.ok {
+        ApiResponse::ok(json!(report), "Ready")
+    } else {
+        ApiResponse::error("NOT_READY", "A dependency check failed".to_string(), json!(report))
+            .with_status(StatusCode::SERVICE_UNAVAILABLE)
+    }
+}
@@ -0,0 +1,17 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use crate::errors::ApiError;
+use crate::response::ApiResponse;
+use crate::services::jobs;
+
+// Handler for the `/jobs/{id}` endpoint, returning the job's status, result or error
+pub async fn get_job(path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let job = jobs::get(&path.into_inner())?;
+    Ok(ApiResponse::ok(json!(job), "Job status"))
+}
+
+// Handler for the `/jobs/{id}/cancel` endpoint
+pub async fn cancel_job(path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let job = jobs::cancel(&path.into_inner())?;
+    Ok(ApiResponse::ok(json!(job), "Job cancelled"))
+}
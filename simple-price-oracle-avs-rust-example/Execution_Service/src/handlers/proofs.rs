@@ -0,0 +1,37 @@
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use crate::errors::ApiError;
+use crate::response::ApiResponse;
+use crate::services::proof_store;
+
+// Handler for the `/proofs/{task_id}/{pair}` endpoint
+//
+// Returns the leaf of `pair` in a submitted batch together with its inclusion
+// path, which can be checked on-chain with OpenZeppelin's `MerkleProof.verify`.
+pub async fn get_inclusion_proof(path: web::Path<(String, String)>) -> Result<HttpResponse, ApiError> {
+    let (task_id, pair) = path.into_inner();
+
+    let batch = proof_store::get(&task_id).ok_or_else(|| ApiError::NotFound("Unknown task id".to_string()))?;
+    let leaf = batch
+        .leaves
+        .iter()
+        .find(|leaf| leaf.pair.eq_ignore_ascii_case(&pair))
+        .ok_or_else(|| ApiError::NotFound("Pair not found in task".to_string()))?;
+
+    let leaf_hash = leaf.hash().map_err(ApiError::Internal)?;
+    let proof = batch
+        .tree
+        .proof(leaf_hash)
+        .ok_or_else(|| ApiError::Internal("Leaf missing from merkle tree".to_string()))?;
+
+    Ok(ApiResponse::ok(
+        json!({
+            "task_id": batch.task_id,
+            "root": batch.root.to_string(),
+            "leaf": leaf,
+            "leaf_hash": leaf_hash.to_string(),
+            "proof": proof.iter().map(|node| node.to_string()).collect::<Vec<String>>(),
+        }),
+        "Inclusion proof",
+    ))
+}
@@ -0,0 +1,22 @@
+use actix_web::{web::Bytes, HttpResponse};
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use std::convert::Infallible;
+
+/// One Server-Sent Event named `name`, with `data` as its JSON payload.
+pub fn event(name: &str, data: &Value) -> Bytes {
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// Response sending `events` to the client as they are produced.
+pub fn response<S>(events: S) -> HttpResponse
+where
+    S: Stream<Item = Bytes> + 'static,
+{
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Keeps reverse proxies such as nginx from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events.map(Ok::<_, Infallible>))
+}
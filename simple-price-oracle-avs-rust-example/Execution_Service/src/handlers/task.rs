@@ -1,118 +1,315 @@
-use actix_web::{web, HttpResponse, Responder};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use crate::services::dal_service; // Import from services/price.rs
+use actix_web::{http::StatusCode, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::errors::ApiError;
+use crate::response::{ApiResponse, ErrorBody};
+use crate::services::dal_service::{self, AgentProof}; // Import from services/price.rs
 use crate::services::oracle_service;  // Import from services/task.rs
-use crate::handlers::openai::{OpenAIAgent, Agent, Message, StableYieldFarmingAgent};
-use std::env;
-use anyhow::Result;
+use crate::services::jobs::{self, JobHandle};
+use crate::services::price_guard;
+use crate::services::task_history::{self, TaskKind, TaskOutcome, TaskRecord};
+use crate::services::task_meta::TaskMeta;
+use crate::services::usage;
+use crate::telemetry;
+use crate::handlers::agent_middleware::{self, MiddlewareConfig};
+use crate::handlers::inputs::{self, AgentInputs, InputError};
+use crate::handlers::llm_cache::CachedAgent;
+use crate::handlers::model_policy::{self, ModelPin};
+use crate::handlers::openai::{Agent, OpenAIAgent, StableYieldFarmingAgent, TokenSink};
+use crate::handlers::prompts;
+use crate::handlers::sse;
+use crate::handlers::tools::LiveTools;
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn, Instrument};
 
 #[derive(Deserialize)]
 pub struct ExecuteTaskPayload {
-    pub taskDefinitionId: Option<i32>, // optional in case it's not included in the request body
+    #[serde(rename = "taskDefinitionId")]
+    pub task_definition_id: Option<i32>, // optional in case it's not included in the request body
 }
 
-#[derive(Serialize)]
-struct CustomResponse {
-    status: String,
-    data: HashMap<String, serde_json::Value>,
+pub async fn execute_task(payload: web::Json<ExecuteTaskPayload>) -> Result<HttpResponse, ApiError> {
+    // The task id is assigned up front so every step of the task is logged under it
+    let meta = TaskMeta::new();
+    let span = telemetry::task_span(&meta.task_id);
+
+    execute_price_task(payload.into_inner(), meta)
+        .instrument(span.clone())
+        .await
+        .inspect_err(|e| span.in_scope(|| e.log()))
 }
 
-pub async fn execute_task(payload: web::Json<ExecuteTaskPayload>) -> impl Responder {
-    println!("Executing Task");
+async fn execute_price_task(payload: ExecuteTaskPayload, meta: TaskMeta) -> Result<HttpResponse, ApiError> {
+    info!("Executing Task");
 
     // Default taskDefinitionId to 0 if not provided
-    let task_definition_id = payload.taskDefinitionId.unwrap_or(0);
-    println!("task_definition_id: {}", task_definition_id);
-
-    match oracle_service::get_price("ETHUSDT").await {
-        Ok(price_data) => {
-            let proof_of_task = price_data.price;
-            // Send the task
-            dal_service::send_task(proof_of_task.clone(), task_definition_id).await;
-            HttpResponse::Ok().json("Task executed successfully".to_string()) // Return the response as JSON
+    let task_definition_id = payload.task_definition_id.unwrap_or(0);
+    info!(task_definition_id);
+
+    let pairs = oracle_service::task_pairs(task_definition_id);
+    let method = oracle_service::PriceMethod::from_env()?;
+    info!(?pairs, ?method, "Fetching prices");
+
+    // Fetch every pair concurrently; the batch is only submitted if all of them succeed
+    let results = join_all(pairs.iter().map(|pair| oracle_service::get_price_by(pair, &method))).await;
+
+    let quotes = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    // Guard against stale, halted and anomalous quotes before anything is signed
+    let checks = join_all(quotes.iter().map(price_guard::check)).await;
+    let mut accepted = Vec::with_capacity(quotes.len());
+    let mut suppressions = Vec::new();
+    for (quote, check) in quotes.iter().zip(checks) {
+        match check {
+            Ok(price) => accepted.push((quote.symbol.clone(), price)),
+            Err(suppression) => suppressions.push(suppression),
         }
-        Err(err) => {
-            // Error fetching price
-            eprintln!("Error fetching price: {}", err);
-            HttpResponse::ServiceUnavailable().json("Network error occurred")
-            
+    }
+    if !suppressions.is_empty() {
+        for suppression in &suppressions {
+            price_guard::record(suppression.clone());
         }
+        return Err(ApiError::Suppressed(suppressions));
+    }
+
+    let timestamp = chrono::Utc::now().timestamp() as u64;
+    let mut prices = Vec::with_capacity(quotes.len());
+    for quote in quotes {
+        prices.push(oracle_service::PriceProof {
+            symbol: quote.symbol,
+            price: quote.price,
+            timestamp,
+            method: method.clone(),
+        });
+    }
+
+    // Record the methodology next to each price so validators can reproduce it
+    let task_id = meta.task_id.clone();
+    let batch = oracle_service::PriceBatchProof { meta: Some(meta), prices };
+
+    // Send the task, with the Merkle root of the prices as its result
+    let root = dal_service::send_batch_task(&batch, task_definition_id).await?;
+
+    // Only prices that reached the aggregator become the reference for later jumps
+    for (pair, price) in accepted {
+        price_guard::accept(&pair, price);
     }
+
+    Ok(ApiResponse::ok(
+        json!({ "task_id": task_id, "merkle_root": root.to_string() }),
+        "Task executed successfully",
+    ))
 }
 
 #[derive(Deserialize)]
 pub struct ExecuteAgentPayload {
-    pub taskDefinitionId: Option<i32>,
+    #[serde(rename = "taskDefinitionId")]
+    pub task_definition_id: Option<i32>,
     pub prices: String,
     pub portfolio: String,
     pub model_name: String,
+    pub webhook_url: Option<String>, // receives the job once it finishes
 }
 
-pub async fn execute_agent(payload: web::Json<ExecuteAgentPayload>) -> impl Responder {
-    println!("Executing Agent");
-
-    let task_definition_id = payload.taskDefinitionId.unwrap_or(0);
-    println!("task_definition_id: {}", task_definition_id);
-
-    // Get OpenAI API key from environment variables
-    let api_key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            eprintln!("OPENAI_API_KEY is not set in environment variables");
-            return HttpResponse::InternalServerError().json("OpenAI API key not configured");
+// Refuses an agent task up front for inputs that do not follow their schema, a model the policy
+// does not allow, or once today's LLM budget is spent
+fn admit_agent_task(payload: &ExecuteAgentPayload, meta: &TaskMeta) -> Result<(AgentInputs, ModelPin), ApiError> {
+    let task_definition_id = payload.task_definition_id.unwrap_or(0);
+    let inputs = match AgentInputs::parse(&payload.portfolio, &payload.prices) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            record_refused_inputs(payload, meta, &e);
+            return Err(e.into());
         }
     };
+    let pin = model_policy::resolve(task_definition_id, &payload.model_name)?;
+    usage::check_budget(task_definition_id)?;
+    Ok((inputs, pin))
+}
 
-    // Create an OpenAI agent
-    let openai_agent = OpenAIAgent::new(
-        api_key,
-        payload.model_name.clone(),
-        0.7,
-    );
+// Keeps a task refused for malformed inputs in the task history, with the flags raised on the text as submitted
+fn record_refused_inputs(payload: &ExecuteAgentPayload, meta: &TaskMeta, e: &InputError) {
+    let raw_flags = inputs::detect_raw(&payload.portfolio, &payload.prices);
+    if !raw_flags.is_empty() {
+        warn!(flags = ?raw_flags, "Refused agent inputs contain instruction-like content");
+    }
 
-    // Create a StableYieldFarmingAgent with the OpenAI agent
-    let farming_agent = StableYieldFarmingAgent::new(openai_agent);
+    let mut record = TaskRecord::new(TaskKind::Agent, payload.task_definition_id.unwrap_or(0), String::new(), TaskOutcome::Refused);
+    record.task_id = Some(meta.task_id.clone());
+    record.inputs = json!({ "prices": payload.prices, "portfolio": payload.portfolio, "model_name": payload.model_name });
+    record.error = Some(ErrorBody { code: e.code().to_string(), message: e.to_string() });
+    record.details = json!({ "raw_input_flags": raw_flags });
+    task_history::record(record);
+}
+
+// Queues the agent task as a job and answers with its id right away; poll `/jobs/{id}` for the outcome
+pub async fn execute_agent(payload: web::Json<ExecuteAgentPayload>) -> Result<HttpResponse, ApiError> {
+    let payload = payload.into_inner();
+    let meta = TaskMeta::new();
+    let span = telemetry::task_span(&meta.task_id);
+
+    let (inputs, pin) = admit_agent_task(&payload, &meta)?;
+    let job = span.in_scope(|| {
+        let webhook_url = payload.webhook_url.clone();
+        jobs::submit(meta.task_id.clone(), webhook_url, move |job| execute_agent_task(payload, inputs, pin, meta, job, None))
+    })?;
+
+    Ok(ApiResponse::success(
+        json!({ "job_id": job.id, "task_id": job.task_id, "status": job.status }),
+        "Agent task queued",
+    )
+    .with_status(StatusCode::ACCEPTED))
+}
+
+// Runs the agent task as a job like `execute_agent`, answering with Server-Sent Events: `job` once
+// it is queued, `token` for each piece of the answer as the model writes it, then `done` with the
+// signed task and its submission status. The job carries on if the client goes away.
+pub async fn execute_agent_stream(payload: web::Json<ExecuteAgentPayload>) -> Result<HttpResponse, ApiError> {
+    let payload = payload.into_inner();
+    let meta = TaskMeta::new();
+    let span = telemetry::task_span(&meta.task_id);
 
-    // Call get_farming_strategy with the provided parameters
-    match farming_agent.get_farming_strategy(&payload.prices, &payload.portfolio).await {
-        Ok(chat_response) => {
-            println!("Input prompt: {}", chat_response.input_prompt);
-            println!("Agent response: {}", chat_response.response);
-            
-            // Send the agent task with both input prompt and response
-            match dal_service::send_agent_task(
-                payload.prices.clone(),
-                payload.portfolio.clone(),
-                payload.model_name.clone(),
-                chat_response.response.clone(),
-                task_definition_id
-            ).await {
-                Ok(_) => {
-                    println!("Successfully sent agent task to DAL service");
-                },
+    let (inputs, pin) = admit_agent_task(&payload, &meta)?;
+    let (tokens, token_events) = mpsc::unbounded_channel();
+    let (done, done_event) = oneshot::channel();
+    let task_id = meta.task_id.clone();
+    let job = span.in_scope(|| {
+        let webhook_url = payload.webhook_url.clone();
+        jobs::submit(meta.task_id.clone(), webhook_url, move |job| async move {
+            let task_id = meta.task_id.clone();
+            let outcome = execute_agent_task(payload, inputs, pin, meta, job, Some(tokens)).await;
+            let _ = done.send(match &outcome {
+                Ok(result) => {
+                    let mut submitted = result.clone();
+                    submitted["status"] = json!("submitted");
+                    submitted
+                }
                 Err(e) => {
-                    eprintln!("Error sending agent task to DAL service: {}", e);
-                    // Continue execution even if sending task fails
+                    let mut failed = json!({
+                        "status": "failed",
+                        "task_id": task_id,
+                        "error": ErrorBody { code: e.code().to_string(), message: e.to_string() },
+                    });
+                    // A task signed but refused by the aggregator is still sent, so it can be resubmitted
+                    if let ApiError::NotSubmitted { signed_task, .. } = e {
+                        failed["signed_task"] = json!(signed_task);
+                    }
+                    failed
+                }
+            });
+            outcome
+        })
+    })?;
+
+    // Tokens until the agent is done with them, then the outcome; a job cancelled or aborted
+    // before it finished drops its sender without an outcome
+    let queued = sse::event("job", &json!({ "job_id": job.id, "task_id": job.task_id, "status": job.status }));
+    let events = stream::unfold((Some(token_events), Some(done_event)), move |(token_events, done_event)| {
+        let task_id = task_id.clone();
+        async move {
+            if let Some(mut token_events) = token_events {
+                if let Some(text) = token_events.recv().await {
+                    return Some((sse::event("token", &json!({ "text": text })), (Some(token_events), done_event)));
                 }
             }
-            
-            // Return both input and output
-            let mut response_data = HashMap::new();
-            response_data.insert("response".to_string(), serde_json::Value::String(chat_response.response));
-            
-            HttpResponse::Ok().json(CustomResponse {
-                status: "success".to_string(),
-                data: response_data,
-            })
-        },
-        Err(err) => {
-            eprintln!("Error calling farming agent: {}", err);
-            HttpResponse::ServiceUnavailable().json("Error calling farming agent")
+            let outcome = done_event?
+                .await
+                .unwrap_or_else(|_| json!({ "status": "cancelled", "task_id": task_id }));
+            Some((sse::event("done", &outcome), (None, None)))
         }
-    }
+    });
+
+    Ok(sse::response(stream::once(async move { queued }).chain(events)))
 }
 
+async fn execute_agent_task(
+    payload: ExecuteAgentPayload,
+    inputs: AgentInputs,
+    pin: ModelPin,
+    meta: TaskMeta,
+    job: JobHandle,
+    tokens: Option<TokenSink>,
+) -> Result<Value, ApiError> {
+    info!("Executing Agent");
+
+    let task_definition_id = payload.task_definition_id.unwrap_or(0);
+    info!(task_definition_id);
+    if !inputs.raw_flags.is_empty() {
+        warn!(flags = ?inputs.flags, raw_flags = ?inputs.raw_flags, "Agent inputs contain instruction-like content");
+    }
+
+    // Jobs queued before the budget was spent must not exceed it
+    usage::check_budget(task_definition_id)?;
 
+    // Create an OpenAI agent authenticated with OPENAI_API_KEY, calling the pinned model version
+    info!(model = %pin.model, model_version = %pin.version, "Model pinned");
+    let mut openai_agent = OpenAIAgent::from_pin(&pin)?;
+    openai_agent.meter_usage(usage::meter(Some(meta.task_id.clone()), task_definition_id, &pin.version));
 
+    // Create a StableYieldFarmingAgent with the OpenAI agent, behind the response cache when LLM_CACHE is set,
+    // with retries, deadlines, rate limiting and circuit breaking on every round sent to the backend
+    let openai_agent = agent_middleware::wrap(openai_agent, "openai", &MiddlewareConfig::from_env());
+    let mut openai_agent = CachedAgent::from_env(openai_agent);
 
+    // Stream the answer to the caller when it listens for the tokens
+    if let Some(tokens) = tokens {
+        openai_agent.stream_to(tokens);
+    }
+    let farming_agent = StableYieldFarmingAgent::new(openai_agent);
+
+    // Render the current strategy prompt from the typed inputs; its version and hash go in the proof
+    let prompt = inputs.render(&prompts::current(prompts::FARMING_STRATEGY)?)?;
+    info!(prompt_version = %prompt.version, prompt_hash = %prompt.hash, "Rendered prompt");
+
+    // Call get_farming_strategy with the rendered prompt; the model may look up live data through the tools
+    let tools = LiveTools::new(&inputs.portfolio);
+    let chat_response = farming_agent.get_farming_strategy(&prompt, &tools).await?;
+    // Prompts and answers may hold portfolio details, so they are only logged at DEBUG
+    debug!(input_prompt = %chat_response.input_prompt, response = %chat_response.response, "Agent exchange");
+    info!(
+        tool_calls = chat_response.tool_calls.len(),
+        prompt_tokens = chat_response.usage.prompt_tokens,
+        completion_tokens = chat_response.usage.completion_tokens,
+        cost_usd = chat_response.usage.cost_usd,
+        "Agent responded"
+    );
+
+    // Past this point the task reaches the aggregator, so the job can no longer be cancelled
+    if !job.start_submitting() {
+        return Err(ApiError::Job(jobs::JobError::NotCancellable(jobs::JobStatus::Cancelled)));
+    }
+
+    // Send the agent task with its canonical inputs and response; the job fails if it was not submitted
+    let proof = AgentProof {
+        meta: &meta,
+        prices: inputs.prices.to_string(),
+        portfolio: inputs.portfolio.to_string(),
+        input_flags: &inputs.flags,
+        raw_input_flags: &inputs.raw_flags,
+        model_name: payload.model_name.clone(),
+        model_pin: &pin,
+        prompt_version: &prompt.version,
+        prompt_hash: &prompt.hash,
+        agent_response: chat_response.response.clone(),
+        tool_calls: &chat_response.tool_calls,
+    };
+    let signed_task = dal_service::send_agent_task(&proof, &chat_response.usage, task_definition_id).await?;
+    info!("Successfully sent agent task to DAL service");
+
+    Ok(json!({
+        "task_id": meta.task_id,
+        "response": chat_response.response,
+        "tool_calls": chat_response.tool_calls,
+        "usage": chat_response.usage,
+        "input_flags": inputs.flags,
+        "raw_input_flags": inputs.raw_flags,
+        "signed_task": signed_task,
+    }))
+}
+
+// Handler for the `/suppressions` endpoint, listing recently suppressed submissions
+pub async fn get_suppressions() -> HttpResponse {
+    ApiResponse::ok(json!({ "suppressions": price_guard::suppressions() }), "Recent suppressions")
+}
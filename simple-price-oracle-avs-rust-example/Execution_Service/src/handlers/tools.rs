@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use avs_common::handlers::tools::{builtin_specs, pair_argument, price_output};
+use crate::handlers::inputs::Portfolio;
+use crate::handlers::openai::{ToolExecutor, ToolSpec};
+use crate::services::oracle_service::{self, PriceMethod};
+
+/// Built-in tools, answered from live market data and the task's portfolio.
+pub struct LiveTools {
+    portfolio: Portfolio,
+}
+
+impl LiveTools {
+    pub fn new(portfolio: &Portfolio) -> Self {
+        LiveTools { portfolio: portfolio.clone() }
+    }
+
+    async fn run(&self, name: &str, arguments: &Value) -> Result<Value, String> {
+        match name {
+            "get_price" => {
+                let pair = pair_argument(arguments)?;
+                let method = PriceMethod::from_env().map_err(|e| e.to_string())?;
+                let quote = oracle_service::get_price_by(&pair, &method)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(price_output(&quote, &method))
+            }
+            "get_portfolio_balances" => Ok(json!({ "holdings": self.portfolio.0 })),
+            "get_funding_rate" => {
+                let rate = oracle_service::get_funding_rate(&pair_argument(arguments)?)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(json!(rate))
+            }
+            _ => Err(format!("Unknown tool {}", name)),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for LiveTools {
+    fn specs(&self) -> Vec<ToolSpec> {
+        builtin_specs()
+    }
+
+    async fn call(&self, name: &str, arguments: &Value) -> Value {
+        self.run(name, arguments).await.unwrap_or_else(|e| json!({ "error": e }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::inputs::AgentInputs;
+
+    #[tokio::test]
+    async fn answers_from_the_portfolio_and_reports_errors() {
+        let inputs = AgentInputs::parse("1.5 ETH, 2000 USDC", "ETH: 2500").unwrap();
+        let tools = LiveTools::new(&inputs.portfolio);
+
+        let balances = tools.call("get_portfolio_balances", &json!({})).await;
+        assert_eq!(balances, json!({ "holdings": inputs.portfolio.0 }));
+
+        // Calls that cannot be answered are reported to the model, without a market data request
+        assert_eq!(tools.call("get_price", &json!({})).await, json!({ "error": "Missing `pair` argument" }));
+        assert_eq!(tools.call("get_funding_rate", &json!({ "pair": "" })).await, json!({ "error": "Missing `pair` argument" }));
+        assert_eq!(tools.call("get_weather", &json!({})).await, json!({ "error": "Unknown tool get_weather" }));
+    }
+}
@@ -1,24 +1,37 @@
 use std::env;
-use actix_web::{web, App, HttpServer, Responder};
+use std::process;
+use actix_web::{web, App, HttpServer};
+use tracing::{error, info};
+use tracing_actix_web::TracingLogger;
+mod errors;
 mod services;
+use avs_common::{response, settings, telemetry};
 
 mod handlers {
+    pub use avs_common::handlers::{agent_middleware, history, inputs, llm_cache, model_policy, openai, prompts, usage};
+    pub mod tools;
     pub mod task;
-    pub mod openai;
+    pub mod sse;
+    pub mod proofs;
+    pub mod jobs;
+    pub mod health;
 }
 
 // Simulate DAL service initialization
 fn init_dal_service() {
+    // Fail fast with a clear message instead of panicking on the first task
+    let performer_address = match services::doctor::check_private_key() {
+        Ok(address) => address,
+        Err(e) => {
+            error!("Invalid operator key: {}", e);
+            process::exit(1);
+        }
+    };
     let private_key = env::var("PRIVATE_KEY").expect("PRIVATE_KEY is not set in environment variables");
-    let eth_rpc_url = env::var("OTHENTIC_CLIENT_RPC_ADDRESS").expect("ETH_RPC_URL is not set in environment variables");
+    let eth_rpc_url = env::var("OTHENTIC_CLIENT_RPC_ADDRESS").expect("OTHENTIC_CLIENT_RPC_ADDRESS is not set in environment variables");
 
-    services::dal_service::init_config(private_key, eth_rpc_url);
-    println!("DAL service initialized.");
-}
-
-// Define a simple health-check endpoint
-async fn health_check() -> impl Responder {
-    "Server is running"
+    services::dal_service::init_config(private_key.trim().to_string(), eth_rpc_url);
+    info!(%performer_address, "DAL service initialized.");
 }
 
 // Main function
@@ -27,23 +40,63 @@ async fn main() -> std::io::Result<()> {
     // Load environment variables (if using dotenv)
     dotenv::dotenv().ok();
 
+    // Initialize tracing
+    telemetry::init("execution-service");
+
     // Get the port from environment variables or default to 4003
     let port: u16 = env::var("PORT")
         .unwrap_or_else(|_| "4003".to_string())
         .parse()
         .expect("PORT must be a valid number");
 
+    // `--check` runs the self-checks and exits instead of starting the server
+    if env::args().any(|arg| arg == "--check") {
+        let report = services::doctor::run().await;
+        report.print();
+        process::exit(if report.ok { 0 } else { 1 });
+    }
+
+    // Refuse to start with a price methodology validators could not reproduce
+    if let Err(e) = services::oracle_service::PriceMethod::from_env() {
+        error!("Invalid price method: {}", e);
+        process::exit(1);
+    }
+
+    // Refuse to start with a signing mode validators would not verify
+    if let Err(e) = services::eip712::SigningMode::from_env() {
+        error!("Invalid signing mode: {}", e);
+        process::exit(1);
+    }
+
     // Initialize DAL service
     init_dal_service();
 
+    // Keep the price cache warm from the Binance trade stream
+    services::price_stream::spawn(services::price_stream::stream_symbols());
+
     // Start the server
-    println!("Server started on port: {}", port);
-    HttpServer::new(|| {
+    info!(port, "Server started");
+    let server = HttpServer::new(|| {
         App::new()
+        .wrap(TracingLogger::default())
         .route("/task/execute", web::post().to(handlers::task::execute_agent))
+        .route("/task/execute/stream", web::post().to(handlers::task::execute_agent_stream))
+        .route("/jobs/{id}", web::get().to(handlers::jobs::get_job))
+        .route("/jobs/{id}/cancel", web::post().to(handlers::jobs::cancel_job))
+        .route("/task/price", web::post().to(handlers::task::execute_task))
+        .route("/proofs/{task_id}/{pair}", web::get().to(handlers::proofs::get_inclusion_proof))
+        .route("/suppressions", web::get().to(handlers::task::get_suppressions))
+        .route("/tasks", web::get().to(handlers::history::get_tasks))
+        .route("/usage", web::get().to(handlers::usage::get_usage))
+        .route("/metrics", web::get().to(handlers::usage::get_metrics))
+        .route("/health", web::get().to(handlers::health::health_check))
+        .route("/ready", web::get().to(handlers::health::ready))
     })
     .bind(("0.0.0.0", port))?
     .run()
-    .await
+    .await;
+
+    telemetry::shutdown();
+    server
 }
 
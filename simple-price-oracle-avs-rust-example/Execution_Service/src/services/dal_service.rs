@@ -1,18 +1,28 @@
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 use reqwest::Client;
 use serde_json::json;
 use alloy::{
-    hex::{self, encode}, primitives::{keccak256, Bytes}, signers::{k256::{ecdsa::SigningKey, elliptic_curve::generic_array::GenericArray}, local::PrivateKeySigner, Signer}
+    hex::{self, encode}, primitives::{Bytes, B256}, signers::{k256::{ecdsa::SigningKey, elliptic_curve::generic_array::GenericArray}, local::PrivateKeySigner, Signer}
 };
-use alloy_sol_types::{SolValue, sol};
+use crate::errors::ApiError;
+use crate::handlers::inputs::InputFlag;
+use crate::handlers::model_policy::ModelPin;
+use crate::handlers::openai::ToolCall;
+use crate::response::ErrorBody;
+use crate::services::{eip712, merkle, proof_store, task_history};
+use crate::services::eip712::{SignerError, SigningMode};
+use crate::services::oracle_service::{OracleError, PriceBatchProof};
+use crate::services::task_history::{TaskKind, TaskOutcome, TaskRecord};
+use crate::services::task_meta::{ReplayReason, SeenSet, TaskMeta};
+use crate::services::usage::TokenUsage;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, info, warn, Instrument};
 
 #[derive(Debug, Deserialize)]
 struct JsonRpcResponse {
-    jsonrpc: String,
     result: Option<serde_json::Value>,
     error: Option<JsonRpcError>,
-    id: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,6 +31,27 @@ struct JsonRpcError {
     message: String,
 }
 
+/// Failure to submit a task to the aggregator.
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("Aggregator request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("Aggregator returned RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Aggregator returned neither a result nor an error")]
+    UnknownResponse,
+}
+
+impl RpcError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RpcError::Transport(_) => "RPC_UNAVAILABLE",
+            RpcError::Rpc { .. } => "RPC_ERROR",
+            RpcError::UnknownResponse => "RPC_INVALID_RESPONSE",
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Config {
     private_key: String,
@@ -37,140 +68,241 @@ impl Config {
 }
 
 // Global Config instance
-static mut CONFIG: Option<Config> = None;
+static CONFIG: OnceLock<Config> = OnceLock::new();
 
 // Set up global Config (can be called once at initialization)
 pub fn init_config(private_key: String, eth_rpc_url: String) {
-    unsafe {
-        CONFIG = Some(Config::new(private_key, eth_rpc_url));
+    if CONFIG.set(Config::new(private_key, eth_rpc_url)).is_err() {
+        warn!("Config is already initialized");
     }
 }
 
-pub async fn send_task(proof_of_task: String, task_definition_id: i32) -> Result<(), Box<dyn Error>> {
-    // Access global Config
-    let config = unsafe {
-        CONFIG.as_ref().expect("Config is not initialized")
-    };
-    let data = "hello";
-    let result = Bytes::from(data.as_bytes().to_vec());
+fn config() -> &'static Config {
+    CONFIG.get().expect("Config is not initialized")
+}
 
-    // let task_definition_id = 0;
+// Keys of recently submitted tasks, so the same task is never signed and sent twice
+static SUBMITTED_TASKS: OnceLock<Mutex<SeenSet>> = OnceLock::new();
+const MAX_SUBMITTED_TASKS: usize = 10_000;
 
-    let decoded_key = hex::decode(&config.private_key).unwrap();
-    println!("decoded_key {:?}", decoded_key);
-    let signing_key = SigningKey::from_bytes(GenericArray::from_slice(&decoded_key)).unwrap();
-    let signer = PrivateKeySigner::from_signing_key(signing_key);
+// Refuses expired tasks and tasks that were already submitted, then remembers the task
+fn remember_submitted(meta: &TaskMeta) -> Result<(), ReplayReason> {
+    if meta.is_expired() {
+        return Err(ReplayReason::ExpiredTask);
+    }
 
-    let performer_address = signer.address();
-    println!("performer_address {:?}", performer_address);
+    let mut submitted = SUBMITTED_TASKS
+        .get_or_init(|| Mutex::new(SeenSet::new(MAX_SUBMITTED_TASKS)))
+        .lock()
+        .unwrap();
+    if !submitted.insert(meta.key()) {
+        return Err(ReplayReason::DuplicateTask);
+    }
 
-    println!("Address {:?}, {:?}, {:?}, {}", proof_of_task, result, performer_address, task_definition_id );
-    let my_values = (proof_of_task.to_string(), &result, performer_address, task_definition_id);
+    Ok(())
+}
 
-    let encoded_data = my_values.abi_encode_params();
+// Performer signer from the configured private key
+fn performer_signer(config: &Config) -> Result<PrivateKeySigner, SignerError> {
+    let decoded_key = hex::decode(&config.private_key).map_err(|e| SignerError::InvalidKey(e.to_string()))?;
+    let signing_key = SigningKey::from_bytes(GenericArray::from_slice(&decoded_key))
+        .map_err(|e| SignerError::InvalidKey(e.to_string()))?;
+    Ok(PrivateKeySigner::from_signing_key(signing_key))
+}
 
-    // println!("encoded_data {:?} ", encoded_data);
-    let message_hash = keccak256(&encoded_data);
-    println!("message_hash {} ", message_hash);
+// Signs `message_hash` and returns the 0x-prefixed signature
+async fn sign(signer: &PrivateKeySigner, message_hash: B256) -> Result<String, SignerError> {
+    let signature = signer
+        .sign_hash(&message_hash)
+        .instrument(tracing::info_span!("sign", %message_hash))
+        .await
+        .map_err(|e| SignerError::Sign(e.to_string()))?;
+    Ok(format!("0x{}", encode(signature.as_bytes())))
+}
 
-    let signature = signer.sign_hash(&message_hash).await?;
-    let signature_bytes = signature.as_bytes();
-    // let serialized_signature = encode(signature_bytes);
-    let serialized_signature = format!("0x{}", encode(signature_bytes));
+/// A task as signed by the performer and sent to the aggregator.
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedTask {
+    pub proof_of_task: String,
+    pub result: String, // hex of the result bytes
+    pub task_definition_id: i32,
+    pub performer: String,
+    pub signature: String,
+}
+
+// Signs the task in `record`, sends it to the aggregator and keeps the outcome in the task history
+async fn sign_and_submit(config: &Config, mut record: TaskRecord, result: Bytes) -> Result<SignedTask, ApiError> {
+    let signer = performer_signer(config)?;
+    let performer_address = signer.address();
+
+    // Legacy mode hashes abi_encode_params, EIP-712 mode hashes a typed Task
+    let message_hash = eip712::signing_hash(
+        SigningMode::from_env()?, &record.proof_of_task, &result, performer_address, record.task_definition_id
+    )?;
+    let serialized_signature = sign(&signer, message_hash).await?;
 
     let params = vec![
-        json!(proof_of_task),
+        json!(record.proof_of_task),
         json!(result),
-        json!(task_definition_id),
+        json!(record.task_definition_id),
         json!(performer_address),
         json!(serialized_signature),
     ];
 
-    // Call the RPC method (sendTask)
-    make_rpc_request(&config.eth_rpc_url, params).await?;
-    
-    Ok(()) 
+    let submitted = make_rpc_request(&config.eth_rpc_url, params).await;
+
+    record.performer = Some(performer_address.to_string());
+    record.result = Some(result.to_string());
+    record.signature = Some(serialized_signature.clone());
+    if let Err(e) = &submitted {
+        record.outcome = TaskOutcome::Failed;
+        record.error = Some(ErrorBody { code: e.code().to_string(), message: e.to_string() });
+    }
+    let signed = SignedTask {
+        proof_of_task: record.proof_of_task.clone(),
+        result: result.to_string(),
+        task_definition_id: record.task_definition_id,
+        performer: performer_address.to_string(),
+        signature: serialized_signature,
+    };
+    task_history::record(record);
+
+    // Callers still get the signed task when the aggregator refused it
+    match submitted {
+        Ok(_) => Ok(signed),
+        Err(source) => Err(ApiError::NotSubmitted { source, signed_task: Box::new(signed) }),
+    }
+}
+
+/// Inputs and outputs of an agent task, serialized as its proof.
+#[derive(Serialize)]
+pub struct AgentProof<'a> {
+    #[serde(flatten)]
+    pub meta: &'a TaskMeta,
+    pub prices: String,    // canonical text of the typed prices
+    pub portfolio: String, // canonical text of the typed portfolio
+    pub input_flags: &'a [InputFlag],
+    #[serde(skip)]
+    pub raw_input_flags: &'a [InputFlag], // kept in the task history only, as validators cannot raise them again
+    pub model_name: String,
+    pub model_pin: &'a ModelPin,
+    pub prompt_version: &'a str,
+    pub prompt_hash: &'a str,
+    pub agent_response: String,
+    pub tool_calls: &'a [ToolCall],
 }
 
 /// Sends a task with proof of AI agent inference
 /// 
 /// This function is specifically designed for sending tasks that involve AI agent inference.
-/// It includes both the input prompt and the agent's response as proof of task execution.
+/// It includes both the inputs and the agent's response as proof of task execution.
 /// 
 /// # Arguments
 /// 
-/// * `input_prompt` - The prompt sent to the AI agent
-/// * `agent_response` - The response received from the AI agent
+/// * `proof` - The task's inputs, model pin, prompt version and hash, response and tool calls
+/// * `usage` - Tokens and cost of the LLM calls, kept in the task history
 /// * `task_definition_id` - The ID of the task definition
 /// 
-pub async fn send_agent_task(
-    prices: String,
-    portfolio: String,
-    model_name: String,
-    agent_response: String,
-    task_definition_id: i32
-) -> Result<(), Box<dyn Error>> {
+/// Returns the task as signed and sent, also carried by `ApiError::NotSubmitted`
+/// when the aggregator did not take it.
+/// 
+pub async fn send_agent_task(proof: &AgentProof<'_>, usage: &TokenUsage, task_definition_id: i32) -> Result<SignedTask, ApiError> {
     // Access global Config
-    let config = unsafe {
-        CONFIG.as_ref().expect("Config is not initialized")
-    };
-    
-    // Create a JSON object containing both input and output
-    let agent_proof = json!({
-        "prices": prices,
-        "portfolio": portfolio,
-        "model_name": model_name,
-        "agent_response": agent_response
-    });
+    let config = config();
+
+    // Every task carries a unique id, a nonce and an expiry
+    remember_submitted(proof.meta).map_err(ApiError::Replay)?;
     
     // Convert to string for the proof
-    let proof_of_task = agent_proof.to_string();
+    let proof_of_task = serde_json::to_value(proof)
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .to_string();
     
     // Create result data - this could be customized based on the agent's response
     // For now, we're using the agent's response as the result data
-    let result = Bytes::from(agent_response.as_bytes().to_vec());
+    let result = Bytes::from(proof.agent_response.as_bytes().to_vec());
 
-    // Get signer and address
-    let decoded_key = hex::decode(&config.private_key).unwrap();
-    let signing_key = SigningKey::from_bytes(GenericArray::from_slice(&decoded_key)).unwrap();
-    let signer = PrivateKeySigner::from_signing_key(signing_key);
-    let performer_address = signer.address();
+    debug!(prices = %proof.prices, portfolio = %proof.portfolio, model_name = %proof.model_name, agent_response = %proof.agent_response, task_definition_id, "Signing agent task");
 
-    println!("Agent task - prices: {}, portfolio: {}, model_name: {}, Output: {}, Address: {:?}, Task ID: {}", 
-             prices, portfolio, model_name, agent_response, performer_address, task_definition_id);
-    
-    // Create the values tuple for encoding
-    let my_values = (proof_of_task.to_string(), &result, performer_address, task_definition_id);
-    let encoded_data = my_values.abi_encode_params();
-    
-    // Hash and sign the data
-    let message_hash = keccak256(&encoded_data);
-    println!("Agent task message hash: {}", message_hash);
-    
-    let signature = signer.sign_hash(&message_hash).await?;
-    let signature_bytes = signature.as_bytes();
-    let serialized_signature = format!("0x{}", encode(signature_bytes));
+    let mut record = TaskRecord::new(TaskKind::Agent, task_definition_id, proof_of_task, TaskOutcome::Submitted);
+    record.task_id = Some(proof.meta.task_id.clone());
+    record.inputs = json!({ "prices": proof.prices, "portfolio": proof.portfolio, "model_name": proof.model_name });
+    record.usage = Some(usage.clone());
+    record.details = json!({ "input_flags": proof.input_flags, "raw_input_flags": proof.raw_input_flags });
 
-    // Prepare RPC parameters
-    let params = vec![
-        json!(proof_of_task),
-        json!(result),
-        json!(task_definition_id),
-        json!(performer_address),
-        json!(serialized_signature),
-    ];
+    // Sign the data and call the RPC method
+    sign_and_submit(config, record, result).await
+}
 
-    // Call the RPC method
-    make_rpc_request(&config.eth_rpc_url, params).await?;
-    
-    Ok(())
+/// Sends a batch price task whose result is the Merkle root of its prices
+///
+/// Each `(pair, price, decimals, timestamp)` entry becomes a leaf, so on-chain
+/// consumers can verify a single price against the root with an inclusion proof.
+/// The batch is kept in the proof store, under its task id, to serve those proofs.
+///
+/// # Arguments
+///
+/// * `batch` - The prices reported by the task
+/// * `task_definition_id` - The ID of the task definition
+///
+pub async fn send_batch_task(batch: &PriceBatchProof, task_definition_id: i32) -> Result<B256, ApiError> {
+    // Access global Config
+    let config = config();
+
+    let meta = batch.meta.as_ref().ok_or(ApiError::Replay(ReplayReason::MissingTaskMetadata))?;
+    remember_submitted(meta).map_err(ApiError::Replay)?;
+
+    let (leaves, tree) = merkle::build_price_tree(&batch.prices).map_err(OracleError::InvalidData)?;
+    let root = tree.root();
+    info!(prices = leaves.len(), merkle_root = %root, "Built price batch");
+
+    let proof_of_task = serde_json::to_string(batch).map_err(|e| ApiError::Internal(e.to_string()))?;
+    let result = Bytes::from(root.to_vec());
+
+    let mut record = TaskRecord::new(TaskKind::Price, task_definition_id, proof_of_task, TaskOutcome::Submitted);
+    record.task_id = Some(meta.task_id.clone());
+    record.inputs = json!({
+        "pairs": batch.prices.iter().map(|price| price.symbol.as_str()).collect::<Vec<_>>(),
+        "method": batch.prices.first().map(|price| &price.method),
+    });
+
+    sign_and_submit(config, record, result).await?;
+
+    proof_store::save(meta.task_id.clone(), root, leaves, tree);
+    Ok(root)
+}
+
+// Signed tasks sent to the aggregator that have not been answered yet
+static IN_FLIGHT_SUBMISSIONS: AtomicUsize = AtomicUsize::new(0);
+
+// Counts a submission as in flight until it is dropped, including on early error returns
+struct InFlightSubmission;
+
+impl InFlightSubmission {
+    fn start() -> Self {
+        IN_FLIGHT_SUBMISSIONS.fetch_add(1, Ordering::SeqCst);
+        InFlightSubmission
+    }
+}
+
+impl Drop for InFlightSubmission {
+    fn drop(&mut self) {
+        IN_FLIGHT_SUBMISSIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Number of task submissions still waiting on the aggregator.
+pub fn in_flight_submissions() -> usize {
+    IN_FLIGHT_SUBMISSIONS.load(Ordering::SeqCst)
 }
 
 // Function for sending the RPC request
-async fn make_rpc_request(rpc_url: &String, params: Vec<serde_json::Value>) -> Result<String, Box<dyn Error>> {
+#[tracing::instrument(name = "submit", skip_all)]
+async fn make_rpc_request(rpc_url: &String, params: Vec<serde_json::Value>) -> Result<String, RpcError> {
+    let _pending = InFlightSubmission::start();
     let client = Client::new();
     
-    println!("Sending task with params: {:?}", params);
+    debug!(?params, "Sending task");
 
     let body = json!({
         "jsonrpc": "2.0",
@@ -191,8 +323,8 @@ async fn make_rpc_request(rpc_url: &String, params: Vec<serde_json::Value>) -> R
     if let Some(result) = rpc_response.result {
         Ok(format!("Task executed successfully with result {:?}", result)) 
     } else if let Some(error) = rpc_response.error {
-        Err(format!("RPC Error {}: {}", error.code, error.message).into())
+        Err(RpcError::Rpc { code: error.code, message: error.message })
     } else {
-        Err("Unknown RPC response".into())
+        Err(RpcError::UnknownResponse)
     }
 }
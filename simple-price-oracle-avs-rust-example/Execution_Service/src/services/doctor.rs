@@ -0,0 +1,252 @@
+use alloy::{
+    hex,
+    primitives::Address,
+    signers::{k256::ecdsa::SigningKey, local::PrivateKeySigner},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::handlers::openai::OpenAIAgent;
+use crate::handlers::model_policy;
+use crate::handlers::prompts;
+use crate::services::{dal_service, oracle_service, proof_store};
+use crate::settings::env_or;
+
+const DEFAULT_MAX_IN_FLIGHT_SUBMISSIONS: usize = 100;
+const DEFAULT_READY_CACHE_SECS: u64 = 30;
+// Largest relative difference between a secondary source and Binance still considered sane
+const MAX_SOURCE_DEVIATION: f64 = 0.05;
+
+/// Result of one self-check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, result: Result<String, String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Check { name: name.to_string(), ok, detail }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub ok: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn print(&self) {
+        for check in &self.checks {
+            println!("[{}] {}: {}", if check.ok { " OK " } else { "FAIL" }, check.name, check.detail);
+        }
+    }
+}
+
+// Checks of the upstream services last run for `/ready`, with the time they ran
+static DEPENDENCY_CHECKS: Mutex<Option<(Instant, Vec<Check>)>> = Mutex::const_new(None);
+
+/// Runs every self-check: operator key, aggregator RPC, oracle sources, LLM backend, prompts and model policy.
+pub async fn run() -> Report {
+    let mut checks = vec![Check::new("private_key", check_private_key().map(|address| address.to_string()))];
+    checks.extend(check_dependencies().await);
+    checks.push(Check::new("prompts", check_prompts()));
+    checks.push(Check::new("model_policy", check_model_policy()));
+
+    Report {
+        ok: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+/// Self-checks plus the runtime state of the proof store and submissions, served at `/ready`.
+/// The aggregator, oracle and LLM checks are reused for `READY_CACHE_SECS`, so probes do not
+/// call every upstream service each time.
+pub async fn readiness() -> Report {
+    let mut checks = vec![Check::new("private_key", check_private_key().map(|address| address.to_string()))];
+    checks.extend(cached_dependencies().await);
+    checks.push(Check::new("prompts", check_prompts()));
+    checks.push(Check::new("model_policy", check_model_policy()));
+    checks.push(Check::new("proof_store", check_proof_store()));
+    checks.push(Check::new("in_flight_submissions", check_in_flight_submissions()));
+
+    Report {
+        ok: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+async fn check_dependencies() -> Vec<Check> {
+    let mut checks = vec![Check::new("aggregator_rpc", check_aggregator_rpc().await)];
+    checks.extend(check_oracle_sources().await);
+    checks.push(Check::new("llm_backend", check_llm_backend().await));
+    checks
+}
+
+// Concurrent probes wait on the lock, so a stale cache is refreshed only once
+async fn cached_dependencies() -> Vec<Check> {
+    let max_age = Duration::from_secs(env_or("READY_CACHE_SECS", DEFAULT_READY_CACHE_SECS));
+
+    let mut cached = DEPENDENCY_CHECKS.lock().await;
+    match cached.as_ref() {
+        Some((checked_at, checks)) if checked_at.elapsed() < max_age => checks.clone(),
+        _ => {
+            let checks = check_dependencies().await;
+            *cached = Some((Instant::now(), checks.clone()));
+            checks
+        }
+    }
+}
+
+fn check_proof_store() -> Result<String, String> {
+    proof_store::len().map(|len| format!("{} of {} batches held", len, proof_store::MAX_BATCHES))
+}
+
+// Submissions still waiting on the aggregator must stay under `MAX_IN_FLIGHT_SUBMISSIONS`
+fn check_in_flight_submissions() -> Result<String, String> {
+    let limit = env_or("MAX_IN_FLIGHT_SUBMISSIONS", DEFAULT_MAX_IN_FLIGHT_SUBMISSIONS);
+    let in_flight = dal_service::in_flight_submissions();
+
+    if in_flight < limit {
+        Ok(format!("{} submissions in flight", in_flight))
+    } else {
+        Err(format!("{} submissions in flight, limit is {}", in_flight, limit))
+    }
+}
+
+/// Parses `PRIVATE_KEY` and, when `OPERATOR_ADDRESS` is set, checks it derives that address.
+pub fn check_private_key() -> Result<Address, String> {
+    let private_key = env::var("PRIVATE_KEY").map_err(|_| "PRIVATE_KEY is not set in environment variables".to_string())?;
+    let decoded_key = hex::decode(private_key.trim()).map_err(|_| "PRIVATE_KEY is not valid hex".to_string())?;
+    if decoded_key.len() != 32 {
+        return Err(format!("PRIVATE_KEY must be 32 bytes, got {}", decoded_key.len()));
+    }
+    let signing_key = SigningKey::from_slice(&decoded_key).map_err(|_| "PRIVATE_KEY is not a valid secp256k1 key".to_string())?;
+    let address = PrivateKeySigner::from_signing_key(signing_key).address();
+
+    if let Ok(expected) = env::var("OPERATOR_ADDRESS") {
+        let expected = Address::from_str(expected.trim()).map_err(|_| "OPERATOR_ADDRESS is not a valid address".to_string())?;
+        if expected != address {
+            return Err(format!("PRIVATE_KEY belongs to {}, expected OPERATOR_ADDRESS {}", address, expected));
+        }
+    }
+
+    Ok(address)
+}
+
+// Any JSON-RPC answer, even an error for an unknown method, shows the aggregator is up
+async fn check_aggregator_rpc() -> Result<String, String> {
+    let rpc_url = env::var("OTHENTIC_CLIENT_RPC_ADDRESS")
+        .map_err(|_| "OTHENTIC_CLIENT_RPC_ADDRESS is not set in environment variables".to_string())?;
+    let body = json!({ "jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": 1 });
+
+    let response = reqwest::Client::new()
+        .post(&rpc_url)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("{} is unreachable: {}", rpc_url, e))?;
+    let answer: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("{} did not answer JSON-RPC: {}", rpc_url, e))?;
+
+    if answer.get("result").is_some() || answer.get("error").is_some() {
+        Ok(format!("{} answered", rpc_url))
+    } else {
+        Err(format!("{} sent an unexpected answer: {}", rpc_url, answer))
+    }
+}
+
+/// Checks that Binance and every secondary source return a positive price for
+/// each configured pair, and that secondary prices stay close to Binance.
+pub async fn check_oracle_sources() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for pair in oracle_service::task_pairs(0) {
+        let reference = oracle_service::get_price(&pair)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|response| response.price.parse::<f64>().map_err(|e| e.to_string()))
+            .and_then(sane_price);
+        checks.push(Check::new(
+            &format!("oracle:binance:{}", pair),
+            reference.clone().map(|price| price.to_string()),
+        ));
+
+        for source in oracle_service::SECONDARY_SOURCES {
+            let result = match oracle_service::get_secondary_price(source, &pair).await {
+                Ok(price) => sane_price(price).and_then(|price| match reference {
+                    Ok(reference) if (price - reference).abs() / reference > MAX_SOURCE_DEVIATION => {
+                        Err(format!("{} is more than {}% away from Binance {}", price, MAX_SOURCE_DEVIATION * 100.0, reference))
+                    }
+                    _ => Ok(price.to_string()),
+                }),
+                Err(e) => Err(e.to_string()),
+            };
+            checks.push(Check::new(&format!("oracle:{}:{}", source, pair), result));
+        }
+    }
+
+    checks
+}
+
+fn sane_price(price: f64) -> Result<f64, String> {
+    if price > 0.0 && price.is_finite() {
+        Ok(price)
+    } else {
+        Err(format!("{} is not a positive price", price))
+    }
+}
+
+/// Checks that the LLM backend serves every model version pinned by the model policy.
+pub async fn check_llm_backend() -> Result<String, String> {
+    let api_key = env::var("OPENAI_API_KEY").map_err(|_| "OPENAI_API_KEY is not set in environment variables".to_string())?;
+    let policy = model_policy::policy().map_err(|e| e.to_string())?;
+    let versions = policy.versions();
+    if versions.is_empty() {
+        return Err("the model policy pins no model".to_string());
+    }
+
+    let mut missing = Vec::new();
+    for version in &versions {
+        if let Err(e) = OpenAIAgent::new(api_key.clone(), version.to_string(), 0.0).check_model().await {
+            missing.push(format!("{} ({})", version, e));
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!("pinned models are not available: {}", missing.join(", ")));
+    }
+    let versions: Vec<&str> = versions.into_iter().collect();
+    Ok(format!("pinned models {} are available", versions.join(", ")))
+}
+
+/// Checks that the prompt template used for agent tasks is loaded.
+pub fn check_prompts() -> Result<String, String> {
+    prompts::current(prompts::FARMING_STRATEGY)
+        .map(|template| format!("{} {} is loaded", template.name, template.version))
+        .map_err(|e| e.to_string())
+}
+
+/// Checks that the model policy loads and allows at least one model.
+pub fn check_model_policy() -> Result<String, String> {
+    let policy = model_policy::policy().map_err(|e| e.to_string())?;
+    if policy.default.is_empty() && policy.task_definitions.is_empty() {
+        return Err("no model is allowed".to_string());
+    }
+    let versions: Vec<&str> = policy.default.iter().map(|pin| pin.version.as_str()).collect();
+    Ok(format!(
+        "default models {}, {} task definition(s) with their own",
+        versions.join(", "),
+        policy.task_definitions.len()
+    ))
+}
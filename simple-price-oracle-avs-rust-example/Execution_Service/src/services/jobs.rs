@@ -0,0 +1,339 @@
+use actix_web::rt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::future::Future;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::AbortHandle;
+use tracing::{info, warn, Instrument, Span};
+use crate::errors::ApiError;
+use crate::response::ErrorBody;
+use crate::settings::env_or;
+
+const DEFAULT_JOB_WORKERS: usize = 4;
+const DEFAULT_JOB_QUEUE_CAPACITY: usize = 100;
+// Finished jobs kept for status lookups
+const MAX_JOBS: usize = 1_000;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failure to queue, find or cancel a job.
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("Unknown job {0}")]
+    NotFound(String),
+    #[error("Job queue is full")]
+    QueueFull,
+    #[error("Job is {0:?} and can no longer be cancelled")]
+    NotCancellable(JobStatus),
+    #[error("Webhook URL {0} is not on WEBHOOK_ALLOWED_HOSTS")]
+    WebhookNotAllowed(String),
+}
+
+impl JobError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            JobError::NotFound(_) => "JOB_NOT_FOUND",
+            JobError::QueueFull => "JOB_QUEUE_FULL",
+            JobError::NotCancellable(_) => "JOB_NOT_CANCELLABLE",
+            JobError::WebhookNotAllowed(_) => "WEBHOOK_NOT_ALLOWED",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    /// The task is being signed and sent to the aggregator; it can no longer be cancelled
+    Submitting,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub task_id: String,
+    pub status: JobStatus,
+    pub result: Option<Value>,
+    pub error: Option<ErrorBody>,
+    pub webhook_url: Option<String>,
+    pub created_at: u64, // unix seconds
+    pub updated_at: u64,
+}
+
+/// Handle given to a running job, to move it into the submitting stage.
+pub struct JobHandle {
+    id: String,
+}
+
+impl JobHandle {
+    /// Marks the job as submitting, returning false if it was cancelled first.
+    pub fn start_submitting(&self) -> bool {
+        let mut store = store().write().unwrap();
+        match store.entries.get_mut(&self.id) {
+            Some(entry) if entry.job.status == JobStatus::Running => {
+                entry.set_status(JobStatus::Submitting);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Entry {
+    job: Job,
+    abort: Option<AbortHandle>,
+}
+
+impl Entry {
+    fn set_status(&mut self, status: JobStatus) {
+        self.job.status = status;
+        self.job.updated_at = now_secs();
+    }
+}
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<String, Entry>,
+    order: VecDeque<String>,
+}
+
+static STORE: OnceLock<RwLock<Store>> = OnceLock::new();
+static WORKERS: OnceLock<Semaphore> = OnceLock::new();
+
+fn store() -> &'static RwLock<Store> {
+    STORE.get_or_init(|| RwLock::new(Store::default()))
+}
+
+// Bounds how many jobs run at once to `JOB_WORKERS`
+fn workers() -> &'static Semaphore {
+    WORKERS.get_or_init(|| Semaphore::new(env_or("JOB_WORKERS", DEFAULT_JOB_WORKERS)))
+}
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Queues `work` for the worker pool and returns the new job right away.
+///
+/// At most `JOB_QUEUE_CAPACITY` jobs may be queued or running. When the job
+/// finishes, its final state is posted to `webhook_url`, if any, which must be
+/// an http(s) URL on one of the `WEBHOOK_ALLOWED_HOSTS`.
+pub fn submit<W, F>(task_id: String, webhook_url: Option<String>, work: W) -> Result<Job, JobError>
+where
+    W: FnOnce(JobHandle) -> F + 'static,
+    F: Future<Output = Result<Value, ApiError>> + 'static,
+{
+    if let Some(url) = &webhook_url {
+        check_webhook_url(url)?;
+    }
+
+    let now = now_secs();
+    let job = Job {
+        id: uuid::Uuid::new_v4().to_string(),
+        task_id,
+        status: JobStatus::Queued,
+        result: None,
+        error: None,
+        webhook_url,
+        created_at: now,
+        updated_at: now,
+    };
+
+    let mut store = store().write().unwrap();
+    let active = store.entries.values().filter(|entry| !entry.job.status.is_finished()).count();
+    if active >= env_or("JOB_QUEUE_CAPACITY", DEFAULT_JOB_QUEUE_CAPACITY) {
+        return Err(JobError::QueueFull);
+    }
+
+    let id = job.id.clone();
+    let handle = JobHandle { id: id.clone() };
+    let task = rt::spawn(
+        async move {
+            let _permit = workers().acquire().await.expect("Job worker pool is never closed");
+            if !set_running(&id) {
+                return;
+            }
+            let outcome = work(handle).await;
+            finish(&id, outcome);
+        }
+        .instrument(Span::current()),
+    );
+
+    store.entries.insert(job.id.clone(), Entry { job: job.clone(), abort: Some(task.abort_handle()) });
+    store.order.push_back(job.id.clone());
+    evict_finished(&mut store);
+
+    info!(job_id = %job.id, "Job queued");
+    Ok(job)
+}
+
+pub fn get(id: &str) -> Result<Job, JobError> {
+    store()
+        .read()
+        .unwrap()
+        .entries
+        .get(id)
+        .map(|entry| entry.job.clone())
+        .ok_or_else(|| JobError::NotFound(id.to_string()))
+}
+
+/// Cancels a queued or running job. Jobs already submitting to the aggregator cannot be cancelled.
+pub fn cancel(id: &str) -> Result<Job, JobError> {
+    let job = {
+        let mut store = store().write().unwrap();
+        let entry = store.entries.get_mut(id).ok_or_else(|| JobError::NotFound(id.to_string()))?;
+        if !matches!(entry.job.status, JobStatus::Queued | JobStatus::Running) {
+            return Err(JobError::NotCancellable(entry.job.status));
+        }
+
+        entry.set_status(JobStatus::Cancelled);
+        if let Some(abort) = entry.abort.take() {
+            abort.abort();
+        }
+        entry.job.clone()
+    };
+
+    info!(job_id = %job.id, "Job cancelled");
+    notify(&job);
+    Ok(job)
+}
+
+// Moves a queued job to running, unless it was cancelled while waiting for a worker
+fn set_running(id: &str) -> bool {
+    let mut store = store().write().unwrap();
+    match store.entries.get_mut(id) {
+        Some(entry) if entry.job.status == JobStatus::Queued => {
+            entry.set_status(JobStatus::Running);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn finish(id: &str, outcome: Result<Value, ApiError>) {
+    let job = {
+        let mut store = store().write().unwrap();
+        let Some(entry) = store.entries.get_mut(id) else {
+            return;
+        };
+        if entry.job.status == JobStatus::Cancelled {
+            return;
+        }
+
+        match outcome {
+            Ok(result) => {
+                entry.job.result = Some(result);
+                entry.set_status(JobStatus::Succeeded);
+            }
+            Err(e) => {
+                e.log();
+                entry.job.error = Some(ErrorBody { code: e.code().to_string(), message: e.to_string() });
+                entry.set_status(JobStatus::Failed);
+            }
+        }
+        entry.abort = None;
+        entry.job.clone()
+    };
+
+    info!(job_id = %job.id, status = ?job.status, "Job finished");
+    notify(&job);
+}
+
+// Drops the oldest finished jobs once more than MAX_JOBS are kept
+fn evict_finished(store: &mut Store) {
+    while store.order.len() > MAX_JOBS {
+        let Some(position) = store
+            .order
+            .iter()
+            .position(|id| store.entries.get(id).is_none_or(|entry| entry.job.status.is_finished()))
+        else {
+            break;
+        };
+        if let Some(id) = store.order.remove(position) {
+            store.entries.remove(&id);
+        }
+    }
+}
+
+// Webhooks only go to the hosts the operator allows, so callers cannot make the
+// service post to internal addresses; no host is allowed unless configured
+fn check_webhook_url(url: &str) -> Result<(), JobError> {
+    if webhook_allowed(url, &env::var("WEBHOOK_ALLOWED_HOSTS").unwrap_or_default()) {
+        Ok(())
+    } else {
+        Err(JobError::WebhookNotAllowed(url.to_string()))
+    }
+}
+
+fn webhook_allowed(url: &str, allowed_hosts: &str) -> bool {
+    reqwest::Url::parse(url).ok().is_some_and(|parsed| {
+        matches!(parsed.scheme(), "http" | "https")
+            && parsed.host_str().is_some_and(|host| {
+                allowed_hosts
+                    .split(',')
+                    .map(str::trim)
+                    .any(|allowed| !allowed.is_empty() && allowed.eq_ignore_ascii_case(host))
+            })
+    })
+}
+
+// Posts the job to its webhook in the background; delivery failures are only logged.
+// Redirects are not followed, since they could lead off the allowed hosts.
+fn notify(job: &Job) {
+    let Some(url) = job.webhook_url.clone() else {
+        return;
+    };
+    let body = json!(job);
+
+    rt::spawn(
+        async move {
+            let client = reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("Webhook client builds with static settings");
+            let result = client
+                .post(&url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!(%url, error = %e, "Job webhook delivery failed");
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhooks_only_go_to_allowed_hosts() {
+        let allowed = "hooks.example.com, Ops.Example.org";
+
+        assert!(webhook_allowed("https://hooks.example.com/jobs", allowed));
+        assert!(webhook_allowed("http://ops.example.org:8080/done", allowed));
+        assert!(!webhook_allowed("http://169.254.169.254/latest/meta-data", allowed));
+        assert!(!webhook_allowed("https://hooks.example.com.evil.io/jobs", allowed));
+        assert!(!webhook_allowed("https://user@localhost/jobs", allowed));
+        assert!(!webhook_allowed("file://hooks.example.com/etc/passwd", allowed));
+        assert!(!webhook_allowed("not a url", allowed));
+        assert!(!webhook_allowed("https://hooks.example.com/jobs", ""));
+    }
+}
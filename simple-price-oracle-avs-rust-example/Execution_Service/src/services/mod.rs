@@ -1,2 +1,6 @@
 pub mod dal_service;
-pub mod oracle_service;
\ No newline at end of file
+pub mod doctor;
+pub mod jobs;
+pub mod price_guard;
+pub mod proof_store;
+pub use avs_common::services::{eip712, merkle, oracle_service, price_stream, task_history, task_meta, usage};
@@ -0,0 +1,316 @@
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crate::services::oracle_service::{self, PriceResponse};
+use tracing::warn;
+use crate::settings::env_or;
+
+const DEFAULT_MAX_AGE_MS: u64 = 60_000;
+const DEFAULT_JUMP_THRESHOLD: f64 = 0.10;
+// One of the secondary sources agreeing is enough, so a single source being down does not block a real move
+const DEFAULT_MIN_CORROBORATIONS: usize = 1;
+const DEFAULT_CORROBORATION_TOLERANCE: f64 = 0.01;
+const DEFAULT_REFERENCE_TTL_SECS: u64 = 3_600;
+const HISTORY_LEN: usize = 20;
+const MAX_SUPPRESSIONS: usize = 1_000;
+
+/// Why a price was kept from being submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReasonCode {
+    NonPositivePrice,
+    StaleQuote,
+    MarketHalted,
+    PriceJump,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suppression {
+    pub pair: String,
+    pub price: String,
+    pub reason: ReasonCode,
+    pub detail: String,
+    pub timestamp: i64,
+}
+
+impl Suppression {
+    fn new(quote: &PriceResponse, reason: ReasonCode, detail: String) -> Self {
+        Suppression {
+            pair: quote.symbol.clone(),
+            price: quote.price.clone(),
+            reason,
+            detail,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+// Recently submitted prices per pair with when they were accepted, the reference for the circuit breaker
+type PriceHistory = HashMap<String, VecDeque<(f64, Instant)>>;
+
+static HISTORY: OnceLock<Mutex<PriceHistory>> = OnceLock::new();
+static SUPPRESSIONS: OnceLock<Mutex<VecDeque<Suppression>>> = OnceLock::new();
+
+fn history() -> &'static Mutex<PriceHistory> {
+    HISTORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn suppression_log() -> &'static Mutex<VecDeque<Suppression>> {
+    SUPPRESSIONS.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+/// Checks a quote before submission.
+///
+/// Rejects zero or negative prices, quotes older than `PRICE_MAX_AGE_MS`, pairs
+/// that are not trading, and moves larger than `PRICE_JUMP_THRESHOLD` relative to
+/// the prices submitted in the last `PRICE_REFERENCE_TTL_SECS` unless
+/// `PRICE_MIN_CORROBORATIONS` secondary sources agree within `PRICE_CORROBORATION_TOLERANCE`.
+/// Quotes without a trade time, such as REST tickers and order book mids, are
+/// aged by the pair's most recent trade.
+pub async fn check(quote: &PriceResponse) -> Result<f64, Suppression> {
+    let price = positive_price(quote)?;
+
+    let trade_time = match quote.timestamp {
+        Some(trade_time) => trade_time,
+        None => match oracle_service::get_last_trade_time(&quote.symbol).await {
+            Ok(trade_time) => trade_time,
+            // A quote whose age cannot be established is not submitted
+            Err(e) => {
+                return Err(Suppression::new(
+                    quote,
+                    ReasonCode::StaleQuote,
+                    format!("Quote age is unknown: {}", e),
+                ))
+            }
+        },
+    };
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    check_age(quote, trade_time, now, env_or("PRICE_MAX_AGE_MS", DEFAULT_MAX_AGE_MS))?;
+
+    match oracle_service::get_market_status(&quote.symbol).await {
+        Ok(status) => check_status(quote, &status)?,
+        // The price itself was fetched, so an unknown status is not a reason to suppress
+        Err(e) => warn!(pair = %quote.symbol, error = %e, "Could not fetch market status"),
+    }
+
+    if let Some(reference) = reference_price(&quote.symbol) {
+        let threshold = env_or("PRICE_JUMP_THRESHOLD", DEFAULT_JUMP_THRESHOLD);
+        // Secondary sources are only asked when the price jumped
+        if relative_change(price, reference) > threshold {
+            let corroborations = count_corroborations(&quote.symbol, price).await;
+            let required = env_or("PRICE_MIN_CORROBORATIONS", DEFAULT_MIN_CORROBORATIONS);
+            check_jump(quote, price, reference, threshold, corroborations, required)?;
+        }
+    }
+
+    Ok(price)
+}
+
+fn positive_price(quote: &PriceResponse) -> Result<f64, Suppression> {
+    match quote.price.parse::<f64>() {
+        Ok(price) if price > 0.0 && price.is_finite() => Ok(price),
+        _ => Err(Suppression::new(
+            quote,
+            ReasonCode::NonPositivePrice,
+            format!("Price {} is not a positive number", quote.price),
+        )),
+    }
+}
+
+// Ages are in milliseconds; a trade time ahead of the local clock counts as fresh
+fn check_age(quote: &PriceResponse, trade_time: u64, now: u64, max_age: u64) -> Result<(), Suppression> {
+    let age = now.saturating_sub(trade_time);
+    if age > max_age {
+        return Err(Suppression::new(
+            quote,
+            ReasonCode::StaleQuote,
+            format!("Quote is {} ms old, maximum is {} ms", age, max_age),
+        ));
+    }
+    Ok(())
+}
+
+fn check_status(quote: &PriceResponse, status: &str) -> Result<(), Suppression> {
+    if status != "TRADING" {
+        return Err(Suppression::new(
+            quote,
+            ReasonCode::MarketHalted,
+            format!("Market status is {}", status),
+        ));
+    }
+    Ok(())
+}
+
+fn relative_change(price: f64, reference: f64) -> f64 {
+    (price - reference).abs() / reference
+}
+
+fn check_jump(
+    quote: &PriceResponse,
+    price: f64,
+    reference: f64,
+    threshold: f64,
+    corroborations: usize,
+    required: usize,
+) -> Result<(), Suppression> {
+    let change = relative_change(price, reference);
+    if change > threshold && corroborations < required {
+        return Err(Suppression::new(
+            quote,
+            ReasonCode::PriceJump,
+            format!(
+                "Moved {:.2}% from {} (threshold {:.2}%), corroborated by {} of {} required sources",
+                change * 100.0,
+                reference,
+                threshold * 100.0,
+                corroborations,
+                required
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Median of the prices of `pair` submitted within the reference TTL. Older prices expire,
+// so a real move no secondary source could confirm stops being suppressed once they do.
+fn reference_price(pair: &str) -> Option<f64> {
+    let ttl = Duration::from_secs(env_or("PRICE_REFERENCE_TTL_SECS", DEFAULT_REFERENCE_TTL_SECS));
+    let history = history().lock().unwrap();
+    median_within(history.get(pair)?, ttl)
+}
+
+fn median_within(accepted: &VecDeque<(f64, Instant)>, ttl: Duration) -> Option<f64> {
+    let mut prices: Vec<f64> = accepted
+        .iter()
+        .filter(|(_, accepted_at)| accepted_at.elapsed() <= ttl)
+        .map(|(price, _)| *price)
+        .collect();
+    if prices.is_empty() {
+        return None;
+    }
+
+    prices.sort_by(|a, b| a.total_cmp(b));
+    Some(prices[prices.len() / 2])
+}
+
+async fn count_corroborations(pair: &str, price: f64) -> usize {
+    let tolerance = env_or("PRICE_CORROBORATION_TOLERANCE", DEFAULT_CORROBORATION_TOLERANCE);
+    let quotes = join_all(
+        oracle_service::SECONDARY_SOURCES
+            .iter()
+            .map(|source| oracle_service::get_secondary_price(source, pair)),
+    )
+    .await;
+
+    let quotes: Vec<f64> = quotes.into_iter().filter_map(|quote| quote.ok()).collect();
+    corroborating(&quotes, price, tolerance)
+}
+
+// Secondary quotes within `tolerance` of `price`
+fn corroborating(quotes: &[f64], price: f64, tolerance: f64) -> usize {
+    quotes
+        .iter()
+        .filter(|other| (*other - price).abs() / price <= tolerance)
+        .count()
+}
+
+/// Adds a submitted price to the history of its pair.
+pub fn accept(pair: &str, price: f64) {
+    let mut history = history().lock().unwrap();
+    let prices = history.entry(pair.to_string()).or_default();
+    prices.push_back((price, Instant::now()));
+    if prices.len() > HISTORY_LEN {
+        prices.pop_front();
+    }
+}
+
+/// Records a suppressed submission with its reason code.
+pub fn record(suppression: Suppression) {
+    warn!(
+        pair = %suppression.pair,
+        price = suppression.price,
+        reason = ?suppression.reason,
+        detail = %suppression.detail,
+        "Suppressed price submission"
+    );
+
+    let mut log = suppression_log().lock().unwrap();
+    log.push_back(suppression);
+    if log.len() > MAX_SUPPRESSIONS {
+        log.pop_front();
+    }
+}
+
+/// Recently suppressed submissions, oldest first.
+pub fn suppressions() -> Vec<Suppression> {
+    suppression_log().lock().unwrap().iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(price: &str) -> PriceResponse {
+        PriceResponse { symbol: "ETHUSDT".to_string(), price: price.to_string(), timestamp: None }
+    }
+
+    fn reason<T>(result: Result<T, Suppression>) -> Option<ReasonCode> {
+        result.err().map(|suppression| suppression.reason)
+    }
+
+    #[test]
+    fn suppresses_non_positive_prices() {
+        for price in ["0", "-1", "abc", "NaN", "inf"] {
+            assert_eq!(reason(positive_price(&quote(price))), Some(ReasonCode::NonPositivePrice), "{}", price);
+        }
+        assert_eq!(positive_price(&quote("2500.5")).unwrap(), 2500.5);
+    }
+
+    #[test]
+    fn suppresses_stale_quotes() {
+        let quote = quote("2500");
+        assert_eq!(reason(check_age(&quote, 1_000, 62_000, 60_000)), Some(ReasonCode::StaleQuote));
+        assert!(check_age(&quote, 1_000, 61_000, 60_000).is_ok());
+        // A trade time ahead of the local clock is not stale
+        assert!(check_age(&quote, 70_000, 61_000, 60_000).is_ok());
+    }
+
+    #[test]
+    fn suppresses_halted_markets() {
+        let quote = quote("2500");
+        assert_eq!(reason(check_status(&quote, "HALT")), Some(ReasonCode::MarketHalted));
+        assert_eq!(reason(check_status(&quote, "BREAK")), Some(ReasonCode::MarketHalted));
+        assert!(check_status(&quote, "TRADING").is_ok());
+    }
+
+    #[test]
+    fn suppresses_uncorroborated_jumps() {
+        let quote = quote("120");
+        assert_eq!(reason(check_jump(&quote, 120.0, 100.0, 0.10, 0, 1)), Some(ReasonCode::PriceJump));
+        assert!(check_jump(&quote, 120.0, 100.0, 0.10, 1, 1).is_ok());
+        assert_eq!(reason(check_jump(&quote, 120.0, 100.0, 0.10, 1, 2)), Some(ReasonCode::PriceJump));
+        // A move within the threshold needs no corroboration
+        assert!(check_jump(&quote, 105.0, 100.0, 0.10, 0, 1).is_ok());
+    }
+
+    #[test]
+    fn counts_secondary_quotes_within_tolerance() {
+        assert_eq!(corroborating(&[120.5, 150.0, 119.0], 120.0, 0.01), 2);
+        assert_eq!(corroborating(&[], 120.0, 0.01), 0);
+    }
+
+    #[test]
+    fn expires_reference_prices() {
+        let now = Instant::now();
+        let old = now.checked_sub(Duration::from_secs(2)).unwrap();
+        let accepted: VecDeque<(f64, Instant)> = [(200.0, old), (100.0, now), (110.0, now), (90.0, now)].into();
+
+        assert_eq!(median_within(&accepted, Duration::from_secs(60)), Some(110.0));
+        // The old price no longer counts once it is past the TTL
+        assert_eq!(median_within(&accepted, Duration::from_secs(1)), Some(100.0));
+        let old_only: VecDeque<(f64, Instant)> = [(50.0, old)].into();
+        assert_eq!(median_within(&old_only, Duration::from_secs(1)), None);
+    }
+}
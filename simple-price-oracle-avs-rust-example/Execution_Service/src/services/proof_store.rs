@@ -0,0 +1,54 @@
+use alloy_primitives::B256;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{OnceLock, RwLock};
+use crate::services::merkle::{MerkleTree, PriceLeaf};
+
+// Number of submitted batches kept for serving inclusion proofs
+pub const MAX_BATCHES: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct MerkleBatch {
+    pub task_id: String,
+    pub root: B256,
+    pub leaves: Vec<PriceLeaf>,
+    pub tree: MerkleTree,
+}
+
+#[derive(Default)]
+struct Store {
+    batches: HashMap<String, MerkleBatch>,
+    order: VecDeque<String>,
+}
+
+static STORE: OnceLock<RwLock<Store>> = OnceLock::new();
+
+fn store() -> &'static RwLock<Store> {
+    STORE.get_or_init(|| RwLock::new(Store::default()))
+}
+
+/// Keeps a submitted batch, evicting the oldest one once the store is full.
+pub fn save(task_id: String, root: B256, leaves: Vec<PriceLeaf>, tree: MerkleTree) {
+    let mut store = store().write().unwrap();
+    let batch = MerkleBatch { task_id: task_id.clone(), root, leaves, tree };
+    if store.batches.insert(task_id.clone(), batch).is_none() {
+        store.order.push_back(task_id);
+    }
+
+    while store.order.len() > MAX_BATCHES {
+        if let Some(oldest) = store.order.pop_front() {
+            store.batches.remove(&oldest);
+        }
+    }
+}
+
+pub fn get(task_id: &str) -> Option<MerkleBatch> {
+    store().read().unwrap().batches.get(task_id).cloned()
+}
+
+/// Number of batches held, or an error if a writer panicked while holding the lock.
+pub fn len() -> Result<usize, String> {
+    store()
+        .read()
+        .map(|store| store.batches.len())
+        .map_err(|_| "Proof store lock is poisoned".to_string())
+}
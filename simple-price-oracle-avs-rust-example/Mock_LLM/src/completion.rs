@@ -0,0 +1,161 @@
+use actix_web::web::Bytes;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::script::{Answer, ScriptedCall};
+
+/// The parts of a chat-completions request the mock reads.
+#[derive(Debug, Deserialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<RequestMessage>,
+    #[serde(default)]
+    pub tools: Option<Vec<Value>>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+impl RequestMessage {
+    /// Text of the message, joining the text parts of multi-part content.
+    pub fn text(&self) -> String {
+        match &self.content {
+            Value::String(text) => text.clone(),
+            Value::Array(parts) => parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        }
+    }
+}
+
+impl ChatRequest {
+    pub fn offers_tools(&self) -> bool {
+        self.tools.as_ref().is_some_and(|tools| !tools.is_empty())
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+
+    fn wants_stream_usage(&self) -> bool {
+        self.stream_options.as_ref().is_some_and(|options| options.include_usage)
+    }
+}
+
+// Roughly four characters per token, which is close enough for budget tests
+fn tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
+
+fn usage(request: &ChatRequest, answer: &Answer) -> Value {
+    let prompt_tokens: u32 = request.messages.iter().map(|message| tokens(&message.text())).sum();
+    let completion_tokens = match answer {
+        Answer::Text(text) => tokens(text),
+        Answer::ToolCalls(calls) => calls.iter().map(|call| tokens(&call.name) + tokens(&call.arguments.to_string())).sum(),
+        Answer::Error { .. } => 0,
+    };
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+fn tool_calls(calls: &[ScriptedCall], sequence: &str) -> Vec<Value> {
+    calls
+        .iter()
+        .enumerate()
+        .map(|(index, call)| {
+            json!({
+                "id": format!("call_{}_{}", sequence, index),
+                "type": "function",
+                "function": { "name": call.name, "arguments": call.arguments.to_string() },
+            })
+        })
+        .collect()
+}
+
+fn finish_reason(answer: &Answer) -> &'static str {
+    match answer {
+        Answer::ToolCalls(_) => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// A `chat.completion` object answering `request`.
+pub fn completion(request: &ChatRequest, id: &str, created: u64, answer: &Answer) -> Value {
+    let message = match answer {
+        Answer::ToolCalls(calls) => json!({ "role": "assistant", "content": null, "tool_calls": tool_calls(calls, id) }),
+        Answer::Text(text) => json!({ "role": "assistant", "content": text }),
+        Answer::Error { .. } => json!({ "role": "assistant", "content": null }),
+    };
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": request.model,
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason(answer), "logprobs": null }],
+        "usage": usage(request, answer),
+    })
+}
+
+/// The `chat.completion.chunk` events of a streamed answer, ending with `[DONE]`.
+/// Text is streamed word by word; tool calls come whole in one chunk.
+pub fn chunks(request: &ChatRequest, id: &str, created: u64, answer: &Answer) -> Vec<Bytes> {
+    let chunk = |choices: Value, usage: Value| {
+        let chunk = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": request.model,
+            "choices": choices,
+            "usage": usage,
+        });
+        Bytes::from(format!("data: {}\n\n", chunk))
+    };
+    let delta = |delta: Value, finish_reason: Value| json!([{ "index": 0, "delta": delta, "finish_reason": finish_reason }]);
+
+    let mut events = vec![chunk(delta(json!({ "role": "assistant", "content": "" }), Value::Null), Value::Null)];
+    match answer {
+        Answer::Text(text) => {
+            for word in text.split_inclusive(' ') {
+                events.push(chunk(delta(json!({ "content": word }), Value::Null), Value::Null));
+            }
+        }
+        Answer::ToolCalls(calls) => {
+            let calls: Vec<Value> = tool_calls(calls, id)
+                .into_iter()
+                .enumerate()
+                .map(|(index, mut call)| {
+                    call["index"] = json!(index);
+                    call
+                })
+                .collect();
+            events.push(chunk(delta(json!({ "tool_calls": calls }), Value::Null), Value::Null));
+        }
+        Answer::Error { .. } => {}
+    }
+    events.push(chunk(delta(json!({}), json!(finish_reason(answer))), Value::Null));
+
+    if request.wants_stream_usage() {
+        events.push(chunk(json!([]), usage(request, answer)));
+    }
+    events.push(Bytes::from("data: [DONE]\n\n"));
+    events
+}
@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use actix_web::{http::StatusCode, web, HttpResponse};
+use futures_util::stream;
+use serde_json::json;
+pub mod completion;
+pub mod script;
+
+use completion::ChatRequest;
+use script::{Answer, Script};
+
+static COMPLETIONS: AtomicU64 = AtomicU64::new(0);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+// Handler for `/v1/chat/completions`, answering with the first matching rule of the script,
+// streamed as Server-Sent Events when the request asks for it
+async fn chat_completions(script: web::Data<Script>, request: web::Json<ChatRequest>) -> HttpResponse {
+    let request = request.into_inner();
+    let (answer, delay) = script.answer(&request);
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    if let Answer::Error { status, body } = &answer {
+        let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        return HttpResponse::build(status).json(body);
+    }
+
+    let id = format!("chatcmpl-mock-{}", COMPLETIONS.fetch_add(1, Ordering::Relaxed) + 1);
+    let created = now_secs();
+    if request.is_stream() {
+        let events = completion::chunks(&request, &id, created, &answer);
+        return HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(stream::iter(events.into_iter().map(Ok::<_, actix_web::Error>)));
+    }
+    HttpResponse::Ok().json(completion::completion(&request, &id, created, &answer))
+}
+
+// Handler for `/v1/models/{id}`; every model exists, so self-checks pass against the mock
+async fn get_model(path: web::Path<String>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "id": path.into_inner(), "object": "model", "created": 0, "owned_by": "mock" }))
+}
+
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Registers the endpoints of the mock, which answer from the `Script` in the app data.
+pub fn routes(config: &mut web::ServiceConfig) {
+    config
+        .route("/v1/chat/completions", web::post().to(chat_completions))
+        .route("/v1/models/{id}", web::get().to(get_model))
+        .route("/health", web::get().to(health));
+}
@@ -0,0 +1,46 @@
+use std::env;
+use std::process;
+use actix_web::{web, App, HttpServer};
+use mock_llm::script::Script;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    // Get the port from environment variables or default to 4100
+    let port: u16 = env::var("PORT")
+        .unwrap_or_else(|_| "4100".to_string())
+        .parse()
+        .expect("PORT must be a valid number");
+
+    // Without a script, every request gets the default templated answer
+    let script = match env::var("MOCK_LLM_SCRIPT").ok().filter(|path| !path.is_empty()) {
+        Some(path) => match Script::load(&path) {
+            Ok(script) => {
+                info!(%path, "Script loaded");
+                script
+            }
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        },
+        None => Script::default_script(),
+    };
+    let script = web::Data::new(script);
+
+    info!(port, "Mock LLM server started");
+    HttpServer::new(move || {
+        App::new()
+            .app_data(script.clone())
+            .configure(mock_llm::routes)
+    })
+    .bind(("0.0.0.0", port))?
+    .run()
+    .await
+}
@@ -0,0 +1,214 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::completion::ChatRequest;
+
+/// Failure to load a script.
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    #[error("Script {path} could not be loaded: {reason}")]
+    Invalid { path: String, reason: String },
+    #[error("Script {path} has a random reply without choices")]
+    NoChoices { path: String },
+}
+
+/// Requests a rule applies to. Every condition that is set must hold.
+#[derive(Debug, Default, Deserialize)]
+pub struct Match {
+    /// Prefix of the requested model, such as `gpt-4o`
+    pub model: Option<String>,
+    /// Text the last message contains
+    pub contains: Option<String>,
+    /// Role of the last message, such as `tool` once tool outputs are sent back
+    pub last_role: Option<String>,
+    /// Whether the request offers tools
+    pub tools: Option<bool>,
+}
+
+impl Match {
+    fn matches(&self, request: &ChatRequest) -> bool {
+        let last = request.messages.last();
+        self.model.as_deref().is_none_or(|model| request.model.starts_with(model))
+            && self.contains.as_deref().is_none_or(|text| last.is_some_and(|message| message.text().contains(text)))
+            && self.last_role.as_deref().is_none_or(|role| last.is_some_and(|message| message.role == role))
+            && self.tools.is_none_or(|tools| tools == request.offers_tools())
+    }
+}
+
+/// A tool call made by a scripted reply.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptedCall {
+    pub name: String,
+    #[serde(default = "empty_arguments")]
+    pub arguments: Value,
+}
+
+fn empty_arguments() -> Value {
+    json!({})
+}
+
+/// How a rule answers.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    /// Always the same text
+    Fixed { content: String },
+    /// Text with `{{model}}`, `{{last_message}}`, `{{message_count}}` and `{{request}}` filled from the request
+    Template { content: String },
+    /// One of `choices` at random, in a repeatable order when `seed` is set
+    Random {
+        choices: Vec<String>,
+        #[serde(default)]
+        seed: Option<u64>,
+    },
+    /// Calls to tools the request offers
+    ToolCalls { calls: Vec<ScriptedCall> },
+    /// An OpenAI error response with the HTTP `status`
+    Fail {
+        status: u16,
+        message: String,
+        #[serde(default)]
+        code: Option<String>,
+        #[serde(default)]
+        error_type: Option<String>,
+    },
+}
+
+/// A reply and the requests it answers.
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    /// Name logged when the rule answers
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, rename = "match")]
+    pub matcher: Match,
+    pub reply: Reply,
+    /// Matching requests the rule answers before it is skipped; every one when unset
+    #[serde(default)]
+    pub times: Option<u32>,
+    /// Delay before answering, to exercise client deadlines
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[derive(Deserialize)]
+struct ScriptFile {
+    rules: Vec<Rule>,
+}
+
+/// What the server sends back for a request.
+#[derive(Debug, Clone)]
+pub enum Answer {
+    Text(String),
+    ToolCalls(Vec<ScriptedCall>),
+    Error { status: u16, body: Value },
+}
+
+/// Rules tried in order; the first that matches answers the request.
+pub struct Script {
+    rules: Vec<Rule>,
+    hits: Vec<AtomicU32>,
+    rngs: Vec<Mutex<StdRng>>,
+    requests: AtomicU64,
+}
+
+impl Script {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let rngs = rules
+            .iter()
+            .map(|rule| match rule.reply {
+                Reply::Random { seed: Some(seed), .. } => StdRng::seed_from_u64(seed),
+                _ => StdRng::from_entropy(),
+            })
+            .map(Mutex::new)
+            .collect();
+        Script {
+            hits: rules.iter().map(|_| AtomicU32::new(0)).collect(),
+            rngs,
+            rules,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Script read from the JSON file at `path`.
+    pub fn load(path: &str) -> Result<Self, ScriptError> {
+        let invalid = |reason: String| ScriptError::Invalid { path: path.to_string(), reason };
+        let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let file: ScriptFile = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        if file.rules.iter().any(|rule| matches!(&rule.reply, Reply::Random { choices, .. } if choices.is_empty())) {
+            return Err(ScriptError::NoChoices { path: path.to_string() });
+        }
+        Ok(Self::new(file.rules))
+    }
+
+    /// Script used without a file: a templated answer that is the same for identical requests.
+    pub fn default_script() -> Self {
+        Self::new(vec![Rule {
+            name: Some("default".to_string()),
+            matcher: Match::default(),
+            reply: Reply::Template {
+                content: "Mock answer from {{model}} to {{message_count}} messages".to_string(),
+            },
+            times: None,
+            delay_ms: 0,
+        }])
+    }
+
+    /// Answer of the first rule matching `request`, with the delay to wait before sending it.
+    pub fn answer(&self, request: &ChatRequest) -> (Answer, Duration) {
+        let sequence = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.matcher.matches(request) {
+                continue;
+            }
+            // A rule that ran out of answers lets the next one match
+            let hits = self.hits[index].fetch_add(1, Ordering::Relaxed);
+            if rule.times.is_some_and(|times| hits >= times) {
+                continue;
+            }
+
+            tracing::info!(rule = rule.name.as_deref().unwrap_or("unnamed"), index, sequence, model = %request.model, "Scripted reply");
+            let answer = match &rule.reply {
+                Reply::Fixed { content } => Answer::Text(content.clone()),
+                Reply::Template { content } => Answer::Text(fill(content, request, sequence)),
+                Reply::Random { choices, .. } => {
+                    let choice = self.rngs[index].lock().unwrap().gen_range(0..choices.len());
+                    Answer::Text(choices[choice].clone())
+                }
+                Reply::ToolCalls { calls } => Answer::ToolCalls(calls.clone()),
+                Reply::Fail { status, message, code, error_type } => Answer::Error {
+                    status: *status,
+                    body: json!({ "error": { "message": message, "type": error_type, "param": null, "code": code } }),
+                },
+            };
+            return (answer, Duration::from_millis(rule.delay_ms));
+        }
+
+        tracing::warn!(sequence, model = %request.model, "No rule matches the request");
+        let body = json!({
+            "error": {
+                "message": "No scripted reply matches the request",
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "no_matching_rule",
+            }
+        });
+        (Answer::Error { status: 400, body }, Duration::ZERO)
+    }
+}
+
+// Fills the template variables of `content` from the request
+fn fill(content: &str, request: &ChatRequest, sequence: u64) -> String {
+    let last_message = request.messages.last().map(|message| message.text()).unwrap_or_default();
+    content
+        .replace("{{model}}", &request.model)
+        .replace("{{last_message}}", &last_message)
+        .replace("{{message_count}}", &request.messages.len().to_string())
+        .replace("{{request}}", &sequence.to_string())
+}
@@ -0,0 +1,35 @@
+use alloy::{
+    network::{EthereumWallet, TransactionBuilder},
+    primitives::{Address, B256, U256},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::TransactionRequest,
+    signers::local::PrivateKeySigner,
+};
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// Sends `amount` wei from the deployer key to `to` and waits for the receipt.
+pub async fn fund(rpc_url: &str, deployer_key: &str, to: Address, amount: U256) -> Result<B256> {
+    let deployer = PrivateKeySigner::from_str(deployer_key).context("Invalid deployer private key")?;
+    let provider = ProviderBuilder::new()
+        .wallet(EthereumWallet::from(deployer))
+        .on_http(rpc_url.parse().context("Invalid RPC URL")?);
+
+    let tx = TransactionRequest::default().with_to(to).with_value(amount);
+    let receipt = provider
+        .send_transaction(tx)
+        .await
+        .with_context(|| format!("Could not send funds to {}", to))?
+        .get_receipt()
+        .await?;
+
+    Ok(receipt.transaction_hash)
+}
+
+pub async fn balance(rpc_url: &str, address: Address) -> Result<U256> {
+    let provider = ProviderBuilder::new().on_http(rpc_url.parse().context("Invalid RPC URL")?);
+    provider
+        .get_balance(address)
+        .await
+        .with_context(|| format!("Could not fetch balance of {}", address))
+}
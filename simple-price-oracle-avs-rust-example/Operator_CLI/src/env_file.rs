@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Sets `KEY=value` entries in an env file, replacing existing keys in place
+/// and appending new ones. Every other line is left untouched.
+pub fn write_entries(path: &Path, entries: &[(String, String)]) -> Result<()> {
+    let existing = if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?
+    } else {
+        String::new()
+    };
+
+    let mut lines: Vec<String> = existing.lines().map(String::from).collect();
+    for (key, value) in entries {
+        let entry = format!("{}={}", key, value);
+        match lines.iter_mut().find(|line| line.split_once('=').map(|(k, _)| k.trim()) == Some(key.as_str())) {
+            Some(line) => *line = entry,
+            None => lines.push(entry),
+        }
+    }
+
+    fs::write(path, lines.join("\n") + "\n").with_context(|| format!("Could not write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("operator-cli-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn replaces_existing_keys_and_appends_new_ones() {
+        let path = temp_file("replace.env");
+        fs::write(&path, "# Performer\nPRIVATE_KEY=old\nOTHER = kept\n\nPORT=4003\n").unwrap();
+
+        let entries = [
+            ("PRIVATE_KEY".to_string(), "new".to_string()),
+            ("OTHER".to_string(), "replaced".to_string()),
+            ("KEYSTORE_DIR".to_string(), "keystores".to_string()),
+        ];
+        write_entries(&path, &entries).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "# Performer\nPRIVATE_KEY=new\nOTHER=replaced\n\nPORT=4003\nKEYSTORE_DIR=keystores\n");
+    }
+
+    #[test]
+    fn creates_a_missing_file() {
+        let path = temp_file("missing.env");
+        let _ = fs::remove_file(&path);
+
+        write_entries(&path, &[("PRIVATE_KEY".to_string(), "abc".to_string())]).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "PRIVATE_KEY=abc\n");
+    }
+}
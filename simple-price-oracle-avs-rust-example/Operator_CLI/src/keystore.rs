@@ -0,0 +1,53 @@
+use alloy::{hex, primitives::Address, signers::local::PrivateKeySigner};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Generates a new key for `role` and stores it encrypted in `dir/<role>`.
+/// An existing keystore is never overwritten.
+pub fn generate(dir: &Path, role: &str, password: &str) -> Result<Address> {
+    if dir.join(role).exists() {
+        bail!("Keystore for {} already exists in {}", role, dir.display());
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+
+    let (signer, _) = PrivateKeySigner::new_keystore(dir, &mut rand::thread_rng(), password, Some(role))
+        .with_context(|| format!("Could not create keystore for {}", role))?;
+    Ok(signer.address())
+}
+
+/// Decrypts the keystore of `role`.
+pub fn load(dir: &Path, role: &str, password: &str) -> Result<PrivateKeySigner> {
+    PrivateKeySigner::decrypt_keystore(dir.join(role), password)
+        .with_context(|| format!("Could not decrypt keystore for {} in {}", role, dir.display()))
+}
+
+/// Private key of `signer` as hex without a 0x prefix, the format the services read.
+pub fn private_key_hex(signer: &PrivateKeySigner) -> String {
+    hex::encode(signer.credential().to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn keystore_round_trip() {
+        let dir = env::temp_dir().join(format!("operator-cli-{}-keystores", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let address = generate(&dir, "performer", "secret").unwrap();
+        let signer = load(&dir, "performer", "secret").unwrap();
+        assert_eq!(signer.address(), address);
+
+        // The exported key decodes back to the same signer
+        let reloaded: PrivateKeySigner = private_key_hex(&signer).parse().unwrap();
+        assert_eq!(reloaded.address(), address);
+
+        assert!(load(&dir, "performer", "wrong").is_err());
+        assert!(generate(&dir, "performer", "secret").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use alloy::primitives::utils::{format_ether, parse_ether};
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+mod chain;
+mod env_file;
+mod keystore;
+
+// Operator roles docker-compose reads keys for, as PRIVATE_KEY_<ROLE>
+const DEFAULT_ROLES: &str = "performer,aggregator,attester1,attester2,attester3";
+
+#[derive(Parser)]
+#[command(about = "Operator key management for the price oracle AVS")]
+struct Cli {
+    /// Directory holding the encrypted operator keystores
+    #[arg(long, global = true, env = "KEYSTORE_DIR", default_value = "keystores")]
+    keystore_dir: PathBuf,
+
+    /// Password used to encrypt and decrypt the keystores
+    #[arg(long, global = true, env = "KEYSTORE_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Roles {
+    /// Comma separated operator roles
+    #[arg(long, value_delimiter = ',', default_value = DEFAULT_ROLES)]
+    roles: Vec<String>,
+}
+
+#[derive(Args)]
+struct Rpc {
+    /// RPC endpoint of the chain the operators are funded on, e.g. a local anvil
+    #[arg(long, env = "RPC_URL", default_value = "http://127.0.0.1:8545")]
+    rpc_url: String,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a key for each role into an encrypted keystore
+    Generate {
+        #[command(flatten)]
+        roles: Roles,
+    },
+    /// Print the address of each role
+    Addresses {
+        #[command(flatten)]
+        roles: Roles,
+    },
+    /// Send ETH from the deployer key to each role
+    Fund {
+        #[command(flatten)]
+        roles: Roles,
+        #[command(flatten)]
+        rpc: Rpc,
+        /// Private key of the funding account
+        #[arg(long, env = "PRIVATE_KEY_DEPLOYER", hide_env_values = true)]
+        deployer_key: String,
+        /// Amount of ETH sent to each role
+        #[arg(long, default_value = "0.02")]
+        amount: String,
+    },
+    /// Print the balance of each role
+    Balances {
+        #[command(flatten)]
+        roles: Roles,
+        #[command(flatten)]
+        rpc: Rpc,
+    },
+    /// Write the PRIVATE_KEY_<ROLE> entries docker-compose expects into an env file
+    Env {
+        #[command(flatten)]
+        roles: Roles,
+        /// Env file to update
+        #[arg(long, default_value = ".env")]
+        output: PathBuf,
+    },
+}
+
+impl Cli {
+    fn password(&self) -> Result<&str> {
+        self.password
+            .as_deref()
+            .context("KEYSTORE_PASSWORD or --password is required")
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Load environment variables (if using dotenv)
+    dotenv::dotenv().ok();
+
+    let cli = Cli::parse();
+    let dir = &cli.keystore_dir;
+
+    match &cli.command {
+        Command::Generate { roles } => {
+            for role in &roles.roles {
+                let address = keystore::generate(dir, role, cli.password()?)?;
+                println!("{}: {}", role, address);
+            }
+        }
+        Command::Addresses { roles } => {
+            for role in &roles.roles {
+                let signer = keystore::load(dir, role, cli.password()?)?;
+                println!("{}: {}", role, signer.address());
+            }
+        }
+        Command::Fund { roles, rpc, deployer_key, amount } => {
+            let amount = parse_ether(amount).context("Invalid amount")?;
+            for role in &roles.roles {
+                let address = keystore::load(dir, role, cli.password()?)?.address();
+                let tx_hash = chain::fund(&rpc.rpc_url, deployer_key, address, amount).await?;
+                println!("{}: sent {} ETH to {} in {}", role, format_ether(amount), address, tx_hash);
+            }
+        }
+        Command::Balances { roles, rpc } => {
+            for role in &roles.roles {
+                let address = keystore::load(dir, role, cli.password()?)?.address();
+                let balance = chain::balance(&rpc.rpc_url, address).await?;
+                println!("{}: {} has {} ETH", role, address, format_ether(balance));
+            }
+        }
+        Command::Env { roles, output } => {
+            let mut entries = Vec::with_capacity(roles.roles.len());
+            for role in &roles.roles {
+                let signer = keystore::load(dir, role, cli.password()?)?;
+                entries.push((
+                    format!("PRIVATE_KEY_{}", role.to_uppercase()),
+                    keystore::private_key_hex(&signer),
+                ));
+            }
+
+            env_file::write_entries(output, &entries)?;
+            println!("Wrote {} keys to {}", entries.len(), output.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::json;
+use tracing::error;
+use crate::handlers::inputs::InputError;
+use crate::handlers::openai::LlmError;
+use crate::handlers::model_policy::PolicyError;
+use crate::handlers::prompts::PromptError;
+use crate::response::ApiResponse;
+use crate::services::backtest::BacktestError;
+use crate::services::oracle_service::OracleError;
+use crate::services::usage::BudgetError;
+use crate::services::validation_service::ValidationError;
+
+/// Every error a handler can return, each with a stable code and HTTP status.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Oracle(#[from] OracleError),
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+    #[error(transparent)]
+    Prompt(#[from] PromptError),
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    Input(#[from] InputError),
+    #[error(transparent)]
+    Backtest(#[from] BacktestError),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Oracle(e) => e.code(),
+            ApiError::Llm(e) => e.code(),
+            ApiError::Prompt(e) => e.code(),
+            ApiError::Policy(e) => e.code(),
+            ApiError::Budget(e) => e.code(),
+            ApiError::Validation(e) => e.code(),
+            ApiError::Input(e) => e.code(),
+            ApiError::Backtest(e) => e.code(),
+        }
+    }
+
+    /// Logs the error with its code, in the current span.
+    pub fn log(&self) {
+        error!(code = self.code(), error = %self, "Request failed");
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Oracle(OracleError::UnknownSource(_) | OracleError::UnsupportedPair(_)) => StatusCode::BAD_REQUEST,
+            ApiError::Oracle(OracleError::UnknownMethod(_) | OracleError::InvalidMethodParameter(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Oracle(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Llm(LlmError::NotConfigured) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Llm(LlmError::Timeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Llm(LlmError::CircuitOpen(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Llm(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Prompt(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Policy(PolicyError::NotAllowed { .. }) => StatusCode::BAD_REQUEST,
+            ApiError::Policy(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Budget(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Input(_) | ApiError::Backtest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiResponse::error(self.code(), self.to_string(), json!({})).with_status(self.status_code())
+    }
+}
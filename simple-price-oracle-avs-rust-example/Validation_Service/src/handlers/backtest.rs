@@ -0,0 +1,29 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use crate::errors::ApiError;
+use crate::handlers::inputs::AgentInputs;
+use crate::response::ApiResponse;
+use crate::services::backtest::{self, BacktestConfig, Limits};
+
+#[derive(Deserialize)]
+pub struct BacktestRequest {
+    pub strategy: String, // an agent answer in the `exchanges`/`positions` format
+    pub portfolio: String,
+    pub prices: String,
+}
+
+// Handler for the `/strategy/backtest` endpoint, stress-testing a strategy as the validator
+// would, with the historical scenarios as well, and reporting the limits it exceeds, without voting on anything
+pub async fn backtest_strategy(request: web::Json<BacktestRequest>) -> Result<HttpResponse, ApiError> {
+    let inputs = AgentInputs::parse(&request.portfolio, &request.prices)?;
+    let strategy = backtest::parse_strategy(&request.strategy)?;
+    let limits = Limits::from_env();
+    let risk = backtest::stress_test_with_history(&strategy, &inputs, &BacktestConfig::from_env()).await?;
+    let breaches = limits.check(&risk);
+
+    Ok(ApiResponse::ok(
+        json!({ "within_limits": breaches.is_empty(), "breaches": breaches, "limits": limits, "risk": risk }),
+        "Strategy backtested",
+    ))
+}
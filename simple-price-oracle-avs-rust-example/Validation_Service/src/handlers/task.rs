@@ -1,167 +1,547 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use log::{info, error};
-use crate::services::validation_service;
-use std::env;
-use crate::handlers::openai::{OpenAIAgent, StableYieldFarmingAgent};
-use serde_json::Value;
+use alloy_primitives::Bytes;
+use serde_json::{json, Value};
+use tracing::{error, info, warn, Instrument};
+use crate::errors::ApiError;
+use crate::response::ApiResponse;
+use crate::services::backtest::{self, BacktestConfig, Breach, Limits, RiskReport};
+use crate::services::task_history::{self, TaskKind, TaskOutcome, TaskRecord};
+use crate::services::validation_service::{self, ValidationError};
+use crate::telemetry;
+use crate::services::task_meta::TaskMeta;
+use crate::services::usage::{self, TokenUsage};
+use crate::handlers::agent_middleware::{self, MiddlewareConfig};
+use crate::handlers::inputs::{self, AgentInputs, InputError, InputFlag};
+use crate::handlers::llm_cache::CachedAgent;
+use crate::handlers::model_policy::{self, ModelPin, PolicyError};
+use crate::handlers::openai::{OpenAIAgent, StableYieldFarmingAgent, ToolCall};
+use crate::handlers::prompts::{self, RenderedPrompt};
+use crate::handlers::tools::{self, OutputMismatch, ReplayTools};
 
 #[derive(Deserialize)]
 pub struct ValidateRequest {
-    pub proofOfTask: String,
+    #[serde(rename = "proofOfTask")]
+    pub proof_of_task: String,
+    pub data: Option<String>,
+    #[serde(rename = "taskDefinitionId")]
+    pub task_definition_id: Option<i32>,
+    pub performer: Option<String>,
+    pub signature: Option<String>,
 }
 
-#[derive(Serialize)]
-pub struct CustomResponse {
-    pub data: serde_json::Value,
-    pub message: String,
-}
+// Handler for the `validate` endpoint
+pub async fn validate_task(request: web::Json<ValidateRequest>) -> Result<HttpResponse, ApiError> {
+    // Log under the task id the performer put in the proof
+    let task_id = validation_service::proof_task_id(&request.proof_of_task);
+    let span = telemetry::task_span(task_id.as_deref().unwrap_or("unknown"));
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    pub data: serde_json::Value,
-    pub error: bool,
-    pub message: String,
+    validate_price_task(request.into_inner())
+        .instrument(span.clone())
+        .await
+        .inspect_err(|e| span.in_scope(|| e.log()))
 }
 
-impl CustomResponse {
-    pub fn new(data: serde_json::Value, message: &str) -> Self {
-        CustomResponse {
-            data,
-            message: message.to_string(),
-        }
+async fn validate_price_task(request: ValidateRequest) -> Result<HttpResponse, ApiError> {
+    let proof_of_task = &request.proof_of_task;
+
+    info!(%proof_of_task, "Validating task");
+
+    // A task without the performer's signature is a vote against it, like a bad signature
+    let (Some(performer), Some(signature)) = (&request.performer, &request.signature) else {
+        error!("Task is not signed by its performer");
+        record_price_vote(&request, false, json!({ "reason": "MISSING_SIGNATURE" }));
+        return Ok(ApiResponse::ok(
+            json!({ "result": false, "reason": "MISSING_SIGNATURE" }),
+            "Task does not carry the performer's signature",
+        ));
+    };
+
+    // Verify the performer's signature
+    let verified = validation_service::verify_signature(
+        proof_of_task,
+        request.data.as_deref(),
+        performer,
+        request.task_definition_id.unwrap_or(0),
+        signature,
+    );
+
+    if !matches!(verified, Ok(true)) {
+        error!(?verified, "Signature verification failed");
+        record_price_vote(&request, false, json!({ "reason": "INVALID_SIGNATURE" }));
+
+        // A bad signature is a vote against the task, not a failure to validate
+        return Ok(ApiResponse::ok(
+            json!({ "result": false, "reason": "INVALID_SIGNATURE" }),
+            "Task signature is not valid for the performer",
+        ));
     }
-}
 
-impl ErrorResponse {
-    pub fn new(data: serde_json::Value, message: &str) -> Self {
-        ErrorResponse {
-            data,
-            error: true, // set error flag to true
-            message: message.to_string(),
-        }
+    let mut validation = validation_service::validate(proof_of_task, request.task_definition_id, request.data.as_deref()).await?;
+    info!(approved = validation.approved, "Vote: {}", if validation.approved { "Approve" } else { "Not Approved" });
+    if !validation.approved {
+        info!(failed = ?validation.failed(), "Failed pairs");
     }
+
+    let data = json!({
+        "result": validation.approved,
+        "failed": validation.failed(),
+        "checks": validation.checks,
+        "merkle_root": validation.merkle_root,
+        "root_matches": validation.root_matches,
+        "reason": validation.rejection,
+    });
+    record_price_vote(&request, validation.approved, data.clone());
+
+    // The vote is recorded, so the task is remembered as seen
+    if let Some(claim) = validation.claim.take() {
+        claim.commit();
+    }
+
+    Ok(ApiResponse::ok(data, "Task validated successfully"))
 }
 
-// Handler for the `validate` endpoint
-pub async fn validate_task(request: web::Json<ValidateRequest>) -> impl Responder {
-    let proof_of_task = &request.proofOfTask;
+// Keeps the vote on a price task in the task history
+fn record_price_vote(request: &ValidateRequest, approved: bool, details: Value) {
+    let outcome = if approved { TaskOutcome::Approved } else { TaskOutcome::Rejected };
+    let mut record = TaskRecord::new(
+        TaskKind::Price,
+        request.task_definition_id.unwrap_or(0),
+        request.proof_of_task.clone(),
+        outcome,
+    );
+    record.task_id = validation_service::proof_task_id(&request.proof_of_task);
+    record.performer = request.performer.clone();
+    record.result = request.data.clone();
+    record.signature = request.signature.clone();
+    record.details = details;
+    task_history::record(record);
+}
 
-    info!("proofOfTask: {}", proof_of_task);
+// Votes against an agent task without calling the model
+fn reject_agent_task(request: &ValidateAgentRequest, proof_of_task: String, reason: &str, message: &str) -> HttpResponse {
+    error!(reason, "Rejected agent task");
+    record_agent_vote(request, proof_of_task, false, json!({ "reason": reason }), None);
 
-    match validation_service::validate(&proof_of_task).await {
-        Ok(result) => {
-            info!("Vote: {}", if result { "Approve" } else { "Not Approved" });
+    ApiResponse::ok(
+        json!({
+            "result": false,
+            "reason": reason,
+            "task_definition_id": request.task_definition_id,
+            "model_name": request.model_name
+        }),
+        message,
+    )
+}
 
-            let response = CustomResponse::new(
-                json!({ "result": result }),
-                "Task validated successfully",
-            );
+// Votes against an agent task whose inputs do not follow their schema, keeping the flags raised on their text
+fn reject_malformed_inputs(request: &ValidateAgentRequest, proof_of_task: String, e: &InputError) -> HttpResponse {
+    let raw_flags = inputs::detect_raw(&request.portfolio, &request.prices);
+    error!(reason = e.code(), ?raw_flags, "Rejected agent task");
+    record_agent_vote(request, proof_of_task, false, json!({ "reason": e.code(), "raw_input_flags": raw_flags }), None);
 
-            HttpResponse::Ok().json(response)
-        }
-        Err(err) => {
-            error!("Validation error: {}", err);
-            
-            let response = ErrorResponse::new(
-                json!({}),
-                "Error during validation step",
-            );
-            
-            HttpResponse::InternalServerError().json(response)
-        }
-    }
+    ApiResponse::ok(
+        json!({
+            "result": false,
+            "reason": e.code(),
+            "task_definition_id": request.task_definition_id,
+            "model_name": request.model_name,
+            "raw_input_flags": raw_flags
+        }),
+        "Task inputs do not follow their schema",
+    )
 }
 
+// Votes against an agent task whose strategy exceeds the risk limits, with its risk report
+fn reject_risky_strategy(request: &ValidateAgentRequest, proof_of_task: String, breaches: &[Breach], risk: &RiskReport) -> HttpResponse {
+    error!(?breaches, "Strategy exceeds the risk limits");
+    let details = json!({ "reason": "STRATEGY_RISK_LIMIT", "breaches": breaches, "risk": risk });
+    record_agent_vote(request, proof_of_task, false, details, None);
+
+    ApiResponse::ok(
+        json!({
+            "result": false,
+            "reason": "STRATEGY_RISK_LIMIT",
+            "task_definition_id": request.task_definition_id,
+            "model_name": request.model_name,
+            "breaches": breaches,
+            "risk": risk
+        }),
+        "Task strategy exceeds the risk limits",
+    )
+}
+
+// Votes against an agent task whose recorded tool outputs this validator cannot confirm
+fn reject_tool_outputs(request: &ValidateAgentRequest, proof_of_task: String, mismatches: &[OutputMismatch]) -> HttpResponse {
+    error!(?mismatches, "Recorded tool outputs do not match");
+    let details = json!({ "reason": "TOOL_OUTPUT_MISMATCH", "mismatches": mismatches });
+    record_agent_vote(request, proof_of_task, false, details, None);
+
+    ApiResponse::ok(
+        json!({
+            "result": false,
+            "reason": "TOOL_OUTPUT_MISMATCH",
+            "task_definition_id": request.task_definition_id,
+            "model_name": request.model_name,
+            "mismatches": mismatches
+        }),
+        "Task tool outputs do not match this validator's data",
+    )
+}
+
+// Keeps the vote on an agent task in the task history
+fn record_agent_vote(request: &ValidateAgentRequest, proof_of_task: String, approved: bool, details: Value, usage: Option<TokenUsage>) {
+    let outcome = if approved { TaskOutcome::Approved } else { TaskOutcome::Rejected };
+    let mut record = TaskRecord::new(TaskKind::Agent, request.task_definition_id, proof_of_task, outcome);
+    record.task_id = request.meta.as_ref().map(|meta| meta.task_id.clone());
+    record.inputs = json!({
+        "prices": request.prices,
+        "portfolio": request.portfolio,
+        "model_name": request.model_name,
+    });
+    record.performer = request.performer.clone();
+    record.result = Some(Bytes::from(request.agent_response.as_bytes().to_vec()).to_string());
+    record.signature = request.signature.clone();
+    record.details = details;
+    record.usage = usage;
+    task_history::record(record);
+}
+
+// Serializes to the fields of the proof the performer signed
 #[derive(Serialize, Deserialize)]
 pub struct ValidateAgentRequest {
     pub prices: String,
     pub portfolio: String,
+    #[serde(default)]
+    pub input_flags: Vec<InputFlag>, // instruction-like content the performer found in the inputs
     pub model_name: String,
+    #[serde(skip_serializing)]
     pub task_definition_id: i32,
+    pub model_pin: Option<ModelPin>, // model version and sampling parameters the performer ran
+    pub prompt_version: Option<String>,
+    pub prompt_hash: Option<String>,
     pub agent_response: String,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>, // tool outputs the performer's model was given
+    #[serde(flatten)]
+    pub meta: Option<TaskMeta>,
+    #[serde(rename = "proofOfTask", skip_serializing)]
+    pub proof_of_task: Option<String>, // proof as signed by the performer
+    #[serde(skip_serializing)]
+    pub performer: Option<String>,
+    #[serde(skip_serializing)]
+    pub signature: Option<String>,
 }
 
-pub async fn validate_agent_task(request: web::Json<ValidateAgentRequest>) -> impl Responder {
-    info!("Validating agent response for task: {}", request.task_definition_id);
-    
-    // Get OpenAI API key from environment variables
-    let api_key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
-            error!("OPENAI_API_KEY is not set in environment variables");
-            let response = ErrorResponse::new(
-                json!({
-                    "task_definition_id": request.task_definition_id,
-                    "model_name": request.model_name
-                }),
-                "OpenAI API key not configured",
-            );
-            return HttpResponse::InternalServerError().json(response);
+pub async fn validate_agent_task(request: web::Json<ValidateAgentRequest>) -> Result<HttpResponse, ApiError> {
+    let task_id = request.meta.as_ref().map(|meta| meta.task_id.clone());
+    let span = telemetry::task_span(task_id.as_deref().unwrap_or("unknown"));
+
+    validate_agent_response(request.into_inner())
+        .instrument(span.clone())
+        .await
+        .inspect_err(|e| span.in_scope(|| e.log()))
+}
+
+async fn validate_agent_response(request: ValidateAgentRequest) -> Result<HttpResponse, ApiError> {
+    info!(task_definition_id = request.task_definition_id, "Validating agent response");
+
+    // A task without the performer's signature over its proof is a vote against it, like a bad signature
+    let (Some(proof_of_task), Some(performer), Some(signature)) = (&request.proof_of_task, &request.performer, &request.signature) else {
+        // Without a signed proof, the task history keeps the fields the request carried
+        let proof_of_task = serde_json::to_string(&request).map_err(|e| ValidationError::InvalidRequest(e.to_string()))?;
+        return Ok(reject_agent_task(&request, proof_of_task, "MISSING_SIGNATURE", "Task does not carry the performer's signature"));
+    };
+    let proof_of_task = proof_of_task.clone();
+
+    // The performer signs the proof with the agent's response as the task result
+    let data = Bytes::from(request.agent_response.as_bytes().to_vec());
+    let verified = validation_service::verify_signature(
+        &proof_of_task,
+        Some(&data.to_string()),
+        performer,
+        request.task_definition_id,
+        signature,
+    );
+    if !matches!(verified, Ok(true)) {
+        error!(?verified, "Signature verification failed");
+        return Ok(reject_agent_task(&request, proof_of_task, "INVALID_SIGNATURE", "Task signature is not valid for the performer"));
+    }
+
+    // The fields checked below must be the ones the performer signed
+    let signed_fields = serde_json::from_str::<Value>(&proof_of_task).ok();
+    let fields = serde_json::to_value(&request).map_err(|e| ValidationError::InvalidRequest(e.to_string()))?;
+    if signed_fields.as_ref() != Some(&fields) {
+        return Ok(reject_agent_task(&request, proof_of_task, "PROOF_MISMATCH", "Task fields differ from its signed proof"));
+    }
+
+    // Refuse inputs that do not follow their schema, or are not in the canonical form the performer records
+    let inputs = match AgentInputs::parse(&request.portfolio, &request.prices) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            warn!(error = %e, "Agent inputs do not parse");
+            return Ok(reject_malformed_inputs(&request, proof_of_task, &e));
         }
     };
+    if inputs.portfolio.to_string() != request.portfolio || inputs.prices.to_string() != request.prices {
+        return Ok(reject_agent_task(&request, proof_of_task, "INPUT_NOT_CANONICAL", "Task inputs do not round-trip through their schema"));
+    }
+    if inputs.flags != request.input_flags {
+        return Ok(reject_agent_task(&request, proof_of_task, "INPUT_FLAGS_MISMATCH", "Task input flags differ from the validator's"));
+    }
+    if !inputs.flags.is_empty() {
+        warn!(flags = ?inputs.flags, "Agent inputs contain instruction-like content");
+    }
 
-    // Create an OpenAI agent using the model_name from the request
-    let openai_agent = OpenAIAgent::new(api_key, request.model_name.clone(), 0.7);
+    // Refuse prompts this validator does not know, or that were rendered differently
+    let template = request
+        .prompt_version
+        .as_deref()
+        .map(|version| prompts::get(prompts::FARMING_STRATEGY, version));
+    let prompt = match template {
+        Some(Ok(template)) => inputs.render(&template)?,
+        _ => {
+            return Ok(reject_agent_task(&request, proof_of_task, "UNKNOWN_PROMPT_VERSION", "Task uses an unknown prompt version"));
+        }
+    };
+    if request.prompt_hash.as_deref() != Some(prompt.hash.as_str()) {
+        return Ok(reject_agent_task(&request, proof_of_task, "PROMPT_HASH_MISMATCH", "Task prompt does not match its version"));
+    }
+
+    // Refuse models this validator does not allow, or that ran with other than the pinned version and parameters
+    let pin = match model_policy::resolve(request.task_definition_id, &request.model_name) {
+        Ok(pin) => pin,
+        Err(PolicyError::NotAllowed { .. }) => {
+            return Ok(reject_agent_task(&request, proof_of_task, "MODEL_NOT_PINNED", "Task uses a model that is not pinned"));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if request.model_pin.as_ref() != Some(&pin) {
+        return Ok(reject_agent_task(&request, proof_of_task, "MODEL_PIN_MISMATCH", "Task model does not match its pin"));
+    }
+
+    // Refuse duplicates and expired tasks before calling the model
+    let claim = match validation_service::check_replay(request.meta.as_ref(), &proof_of_task) {
+        Ok(claim) => claim,
+        Err(reason) => {
+            return Ok(reject_agent_task(&request, proof_of_task, reason.code(), "Task rejected as a replay"));
+        }
+    };
+
+    // Every successful response records a vote, and only then is the task remembered as seen.
+    // Errors such as a spent budget or an unreachable model leave it free to be submitted again.
+    let response = vote_on_strategy(&request, proof_of_task, &inputs, &prompt, &pin).await?;
+    claim.commit();
+    Ok(response)
+}
+
+// Stress tests the strategy, re-runs the model on the same prompt and records the vote
+async fn vote_on_strategy(
+    request: &ValidateAgentRequest,
+    proof_of_task: String,
+    inputs: &AgentInputs,
+    prompt: &RenderedPrompt,
+    pin: &ModelPin,
+) -> Result<HttpResponse, ApiError> {
+    // Refuse strategies whose losses under stress exceed the configured limits, before calling the model
+    let limits = Limits::from_env();
+    let risk = if limits.is_empty() {
+        None
+    } else {
+        let report = match backtest::parse_strategy(&request.agent_response) {
+            Ok(strategy) => backtest::stress_test(&strategy, inputs, &BacktestConfig::from_env()),
+            Err(e) => Err(e),
+        };
+        match report {
+            Ok(report) => {
+                let breaches = limits.check(&report);
+                if !breaches.is_empty() {
+                    return Ok(reject_risky_strategy(request, proof_of_task, &breaches, &report));
+                }
+                info!(worst_loss_usd = report.worst_loss_usd, net_delta_usd = report.net_delta_usd, "Strategy within the risk limits");
+                Some(report)
+            }
+            Err(e) => {
+                warn!(error = %e, "Strategy cannot be backtested");
+                return Ok(reject_agent_task(request, proof_of_task, e.code(), "Task strategy cannot be backtested"));
+            }
+        }
+    };
+
+    // Refuse recorded tool outputs that this validator's own data does not confirm, before calling the model
+    let mismatches = tools::check_recorded_outputs(&request.tool_calls, &inputs.portfolio).await;
+    if !mismatches.is_empty() {
+        return Ok(reject_tool_outputs(request, proof_of_task, &mismatches));
+    }
+
+    // Refuse to re-run the model once today's LLM budget is spent
+    usage::check_budget(request.task_definition_id)?;
+    
+    // Create an OpenAI agent calling the pinned model version
+    let task_id = request.meta.as_ref().map(|meta| meta.task_id.clone());
+    let mut openai_agent = OpenAIAgent::from_pin(pin)?;
+    openai_agent.meter_usage(usage::meter(task_id, request.task_definition_id, &pin.version));
+
+    // Create a StableYieldFarmingAgent with the OpenAI agent, behind the response cache when LLM_CACHE is set,
+    // with retries, deadlines, rate limiting and circuit breaking on every round sent to the backend
+    let openai_agent = agent_middleware::wrap(openai_agent, "openai", &MiddlewareConfig::from_env());
+    let farming_agent = StableYieldFarmingAgent::new(CachedAgent::from_env(openai_agent));
+    
+    // Get a farming strategy using the agent, answering its tool calls with the performer's recorded outputs
+    let tools = ReplayTools::new(request.tool_calls.clone());
+    let strategy_response = farming_agent.get_farming_strategy(prompt, &tools).await?;
+
+    // Clean up both responses by removing whitespace for comparison
+    let agent_response_clean = request.agent_response.trim().to_string();
+    let strategy_response_clean = strategy_response.response.trim().to_string();
+    
+    // Calculate similarity score (percentage of matching characters)
+    let similarity_score = if !agent_response_clean.is_empty() && !strategy_response_clean.is_empty() {
+        // Simple length comparison as a basic similarity metric
+        let min_len = agent_response_clean.len().min(strategy_response_clean.len());
+        let max_len = agent_response_clean.len().max(strategy_response_clean.len());
+        (min_len as f64 / max_len as f64) * 100.0
+    } else {
+        0.0
+    };
+    
+    // Define a threshold for similarity (50% similarity required)
+    const SIMILARITY_THRESHOLD: f64 = 50.0;
+    
+    // The model must have made exactly the recorded tool calls, or the performer's answer rests on other data
+    let transcript_matches = tools.unmatched() == 0 && tools.unused() == 0;
+
+    // Consider the response valid if it's not empty, meets the similarity threshold and replayed the whole transcript
+    let is_valid = !agent_response_clean.is_empty() && similarity_score >= SIMILARITY_THRESHOLD && transcript_matches;
+    
+    info!(approved = is_valid, "Agent validation result: {}", if is_valid { "Approved" } else { "Not Approved" });
+    info!(similarity_score, threshold = SIMILARITY_THRESHOLD, "Similarity score");
 
-    // Create a StableYieldFarmingAgent with the OpenAI agent
-    let farming_agent = StableYieldFarmingAgent::new(openai_agent);
+    let validation_details = json!({
+        "similarity_score": similarity_score,
+        "threshold": SIMILARITY_THRESHOLD,
+        "meets_threshold": similarity_score >= SIMILARITY_THRESHOLD,
+        "tool_calls": strategy_response.tool_calls.len(),
+        "unmatched_tool_calls": tools.unmatched(),
+        "unused_tool_calls": tools.unused(),
+        "reason": (!transcript_matches).then_some("TOOL_TRANSCRIPT_MISMATCH"),
+        "risk": risk
+    });
+    record_agent_vote(request, proof_of_task, is_valid, validation_details.clone(), Some(strategy_response.usage.clone()));
     
-    // Get a farming strategy using the agent
-    match farming_agent.get_farming_strategy(&request.prices, &request.portfolio).await {
-        Ok(strategy_response) => {
-            // Clean up both responses by removing whitespace for comparison
-            let agent_response_clean = request.agent_response.trim().to_string();
-            let strategy_response_clean = strategy_response.response.trim().to_string();
-            
-            // Calculate similarity score (percentage of matching characters)
-            let similarity_score = if !agent_response_clean.is_empty() && !strategy_response_clean.is_empty() {
-                // Simple length comparison as a basic similarity metric
-                let min_len = agent_response_clean.len().min(strategy_response_clean.len());
-                let max_len = agent_response_clean.len().max(strategy_response_clean.len());
-                (min_len as f64 / max_len as f64) * 100.0
-            } else {
-                0.0
-            };
-            
-            // Define a threshold for similarity (50% similarity required)
-            const SIMILARITY_THRESHOLD: f64 = 50.0;
-            
-            // Consider the response valid if it's not empty and meets the similarity threshold
-            let is_valid = !agent_response_clean.is_empty() && similarity_score >= SIMILARITY_THRESHOLD;
-            
-            info!("Agent validation result: {}", if is_valid { "Approved" } else { "Not Approved" });
-            info!("Similarity score: {:.2}%, threshold: {:.2}%", similarity_score, SIMILARITY_THRESHOLD);
-            
-            let response = CustomResponse::new(
-                json!({ 
-                    "result": is_valid,
-                    "task_definition_id": request.task_definition_id,
-                    "model_name": request.model_name,
-                    "validation_details": {
-                        "similarity_score": similarity_score,
-                        "threshold": SIMILARITY_THRESHOLD,
-                        "meets_threshold": similarity_score >= SIMILARITY_THRESHOLD
-                    }
-                }),
-                "Agent response validated successfully",
-            );
-            
-            HttpResponse::Ok().json(response)
-        },
-        Err(err) => {
-            error!("Error generating validation strategy: {}", err);
-            
-            let response = ErrorResponse::new(
-                json!({
-                    "task_definition_id": request.task_definition_id,
-                    "model_name": request.model_name
-                }),
-                &format!("Error during strategy generation: {}", err),
-            );
-            
-            HttpResponse::InternalServerError().json(response)
+    Ok(ApiResponse::ok(
+        json!({ 
+            "result": is_valid,
+            "task_definition_id": request.task_definition_id,
+            "model_name": request.model_name,
+            "validation_details": validation_details
+        }),
+        "Agent response validated successfully",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpServer};
+    use crate::services::eip712::{self, SigningMode};
+    use alloy_signer::SignerSync;
+    use alloy_signer_local::PrivateKeySigner;
+    use crate::settings::EnvGuard;
+    use mock_llm::script::Script;
+
+    // Serves the mock LLM with its default script on a free local port, returning its base URL
+    fn start_mock_llm() -> String {
+        let script = web::Data::new(Script::default_script());
+        let server = HttpServer::new(move || App::new().app_data(script.clone()).configure(mock_llm::routes))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}/v1", address)
+    }
+
+    // Runs the task as the performer does and builds the request it sends to validators
+    async fn performed_task(portfolio: &str, prices: &str) -> ValidateAgentRequest {
+        let inputs = AgentInputs::parse(portfolio, prices).unwrap();
+        let pin = model_policy::resolve(0, "gpt-4o").unwrap();
+        let prompt = inputs.render(&prompts::current(prompts::FARMING_STRATEGY).unwrap()).unwrap();
+        let agent = StableYieldFarmingAgent::new(OpenAIAgent::from_pin(&pin).unwrap());
+        let response = agent.get_farming_strategy(&prompt, &ReplayTools::new(Vec::new())).await.unwrap();
+
+        ValidateAgentRequest {
+            prices: inputs.prices.to_string(),
+            portfolio: inputs.portfolio.to_string(),
+            input_flags: inputs.flags,
+            model_name: pin.model.clone(),
+            task_definition_id: 0,
+            model_pin: Some(pin),
+            prompt_version: Some(prompt.version),
+            prompt_hash: Some(prompt.hash),
+            agent_response: response.response,
+            tool_calls: response.tool_calls,
+            meta: Some(TaskMeta::new()),
+            proof_of_task: None,
+            performer: None,
+            signature: None,
         }
     }
+
+    // Signs the request's fields as its proof, as the performer does
+    fn signed(mut request: ValidateAgentRequest) -> ValidateAgentRequest {
+        let signer = PrivateKeySigner::random();
+        let proof_of_task = serde_json::to_value(&request).unwrap().to_string();
+        let data = Bytes::from(request.agent_response.as_bytes().to_vec());
+        let hash = eip712::signing_hash(SigningMode::Legacy, &proof_of_task, &data, signer.address(), request.task_definition_id).unwrap();
+
+        request.signature = Some(signer.sign_hash_sync(&hash).unwrap().to_string());
+        request.performer = Some(signer.address().to_string());
+        request.proof_of_task = Some(proof_of_task);
+        request
+    }
+
+    async fn vote(request: ValidateAgentRequest) -> Value {
+        let response = validate_agent_response(request).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()["data"].clone()
+    }
+
+    #[actix_web::test]
+    async fn validates_agent_tasks_against_the_mock_llm() {
+        let mock_llm = start_mock_llm();
+        let _env = EnvGuard::set(&[
+            ("OPENAI_BASE_URL", &mock_llm),
+            ("OPENAI_API_KEY", "test"),
+            ("LLM_USAGE_PATH", ""),
+            ("TASK_HISTORY_PATH", ""),
+            ("PROMPTS_DIR", concat!(env!("CARGO_MANIFEST_DIR"), "/../prompts")),
+            ("MODEL_POLICY_PATH", concat!(env!("CARGO_MANIFEST_DIR"), "/../model_policy.json")),
+        ]);
+
+        // The mock answers the same prompt the same way, so the validator agrees with the performer
+        let request = performed_task("1.5 ETH, 2000 USDC", "ETH: 2500, USDC: 1").await;
+        assert!(request.agent_response.starts_with("Mock answer from gpt-4o-2024-08-06"));
+        let approved = vote(signed(request)).await;
+        assert_eq!(approved["result"], true, "{}", approved);
+
+        // An answer the model did not give is voted against, even when the performer signed it
+        let mut tampered = performed_task("1.5 ETH, 2000 USDC", "ETH: 2500, USDC: 1").await;
+        tampered.agent_response = tampered.agent_response.repeat(3);
+        let rejected = vote(signed(tampered)).await;
+        assert_eq!(rejected["result"], false, "{}", rejected);
+        assert_eq!(rejected["validation_details"]["meets_threshold"], false);
+
+        // Unsigned tasks, and fields that differ from the signed proof, are voted against before the model runs
+        let unsigned = vote(performed_task("1.5 ETH, 2000 USDC", "ETH: 2500, USDC: 1").await).await;
+        assert_eq!(unsigned["reason"], "MISSING_SIGNATURE");
+
+        let mut altered = signed(performed_task("1.5 ETH, 2000 USDC", "ETH: 2500, USDC: 1").await);
+        altered.portfolio = "2 ETH, 2000 USDC".to_string();
+        assert_eq!(vote(altered).await["reason"], "PROOF_MISMATCH");
+
+        let mut forged = signed(performed_task("1.5 ETH, 2000 USDC", "ETH: 2500, USDC: 1").await);
+        forged.performer = Some(PrivateKeySigner::random().address().to_string());
+        assert_eq!(vote(forged).await["reason"], "INVALID_SIGNATURE");
+    }
 }
@@ -0,0 +1,295 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use avs_common::handlers::tools::{builtin_specs, pair_argument};
+use crate::handlers::inputs::Portfolio;
+use crate::handlers::openai::{ToolCall, ToolExecutor, ToolSpec};
+use crate::services::oracle_service::{self, FundingRate, PriceMethod};
+use crate::services::volatility;
+use crate::settings::env_or;
+
+// Largest difference between a recorded funding rate and this validator's, as a fraction
+const DEFAULT_FUNDING_RATE_TOLERANCE: f64 = 0.0001;
+
+/// Built-in tools answered from a recorded transcript, so a validator's model
+/// sees the same outputs as the performer's did.
+pub struct ReplayTools {
+    transcript: Vec<ToolCall>,
+    used: Mutex<Vec<bool>>,
+    unmatched: AtomicUsize,
+}
+
+impl ReplayTools {
+    pub fn new(transcript: Vec<ToolCall>) -> Self {
+        let used = Mutex::new(vec![false; transcript.len()]);
+        ReplayTools { transcript, used, unmatched: AtomicUsize::new(0) }
+    }
+
+    /// Calls that had no recorded output.
+    pub fn unmatched(&self) -> usize {
+        self.unmatched.load(Ordering::SeqCst)
+    }
+
+    /// Recorded calls the model never made.
+    pub fn unused(&self) -> usize {
+        self.used.lock().unwrap().iter().filter(|used| !**used).count()
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for ReplayTools {
+    fn specs(&self) -> Vec<ToolSpec> {
+        builtin_specs()
+    }
+
+    // Serves the first unused recorded call with the same name and arguments
+    async fn call(&self, name: &str, arguments: &Value) -> Value {
+        let mut used = self.used.lock().unwrap();
+        let recorded = self
+            .transcript
+            .iter()
+            .enumerate()
+            .find(|(i, call)| !used[*i] && call.name == name && call.arguments == *arguments);
+
+        match recorded {
+            Some((i, call)) => {
+                used[i] = true;
+                call.output.clone()
+            }
+            None => {
+                self.unmatched.fetch_add(1, Ordering::SeqCst);
+                json!({ "error": "No recorded output for this call" })
+            }
+        }
+    }
+}
+
+/// A recorded tool output that this validator's own data does not confirm.
+#[derive(Debug, Serialize)]
+pub struct OutputMismatch {
+    pub tool: String,
+    pub arguments: Value,
+    pub reason: String,
+}
+
+/// Checks every recorded output against this validator's own data: `get_price`
+/// against the oracle, with the recorded method and within the pair's tolerance
+/// band, `get_funding_rate` against Binance futures, and `get_portfolio_balances`
+/// against the task's portfolio. A recorded error must be one this validator
+/// gets as well.
+pub async fn check_recorded_outputs(transcript: &[ToolCall], portfolio: &Portfolio) -> Vec<OutputMismatch> {
+    let mut mismatches = Vec::new();
+
+    for call in transcript {
+        if let Err(reason) = check_recorded_call(call, portfolio).await {
+            mismatches.push(OutputMismatch {
+                tool: call.name.clone(),
+                arguments: call.arguments.clone(),
+                reason,
+            });
+        }
+    }
+
+    mismatches
+}
+
+async fn check_recorded_call(call: &ToolCall, portfolio: &Portfolio) -> Result<(), String> {
+    let recorded_error = call.output.get("error").is_some();
+
+    match call.name.as_str() {
+        "get_portfolio_balances" if recorded_error => Err("Recorded an error for the task's own portfolio".to_string()),
+        "get_portfolio_balances" if call.output != json!({ "holdings": portfolio.0 }) => {
+            Err("Balances differ from the task's portfolio".to_string())
+        }
+        "get_portfolio_balances" => Ok(()),
+        "get_price" if recorded_error => check_recorded_error(&call.arguments, |pair| async move {
+            oracle_service::get_price_by(&pair, &PriceMethod::Last).await.map(|_| ())
+        })
+        .await,
+        "get_price" => check_recorded_price(&call.arguments, &call.output).await,
+        "get_funding_rate" if recorded_error => check_recorded_error(&call.arguments, |pair| async move {
+            oracle_service::get_funding_rate(&pair).await.map(|_| ())
+        })
+        .await,
+        "get_funding_rate" => check_recorded_funding_rate(&call.arguments, &call.output).await,
+        // Tools this validator does not offer can only have failed
+        _ if recorded_error => Ok(()),
+        name => Err(format!("Recorded an output for unknown tool {}", name)),
+    }
+}
+
+// A recorded error is confirmed when the arguments are invalid, or when this validator's lookup fails too
+async fn check_recorded_error<F, Fut>(arguments: &Value, lookup: F) -> Result<(), String>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: std::future::Future<Output = Result<(), oracle_service::OracleError>>,
+{
+    let Ok(pair) = pair_argument(arguments) else {
+        return Ok(());
+    };
+    match lookup(pair.clone()).await {
+        Ok(()) => Err(format!("Recorded an error, but {} is available", pair)),
+        Err(_) => Ok(()),
+    }
+}
+
+// The parts of a recorded price that can be checked without market data
+fn parse_recorded_price(arguments: &Value, output: &Value) -> Result<(String, f64, PriceMethod), String> {
+    let pair = pair_argument(arguments)?;
+    let symbol = output.get("symbol").and_then(Value::as_str).ok_or("Recorded price has no symbol")?;
+    if !symbol.eq_ignore_ascii_case(&pair) {
+        return Err(format!("Recorded price is for {}, not the requested {}", symbol, pair));
+    }
+    let recorded = output
+        .get("price")
+        .and_then(Value::as_str)
+        .and_then(|price| f64::from_str(price).ok())
+        .ok_or("Recorded price is not a number")?;
+    let method = PriceMethod::deserialize(output).map_err(|_| "Recorded price has no valid method".to_string())?;
+
+    Ok((pair, recorded, method))
+}
+
+async fn check_recorded_price(arguments: &Value, output: &Value) -> Result<(), String> {
+    let (pair, recorded, method) = parse_recorded_price(arguments, output)?;
+
+    let expected = oracle_service::get_price_by(&pair, &method)
+        .await
+        .map_err(|e| format!("Error fetching price data: {}", e))?
+        .price
+        .parse::<f64>()
+        .map_err(|_| "Invalid price data from Oracle".to_string())?;
+
+    let band = volatility::tolerance_band(&pair, expected).await;
+    if band.contains(recorded) {
+        Ok(())
+    } else {
+        Err(format!("Recorded price {} of {} is outside [{}, {}]", recorded, pair, band.lower, band.upper))
+    }
+}
+
+// The parts of a recorded funding rate that can be checked without market data
+fn parse_recorded_funding_rate(arguments: &Value, output: &Value) -> Result<(String, FundingRate), String> {
+    let pair = pair_argument(arguments)?;
+    let recorded = FundingRate::deserialize(output).map_err(|_| "Recorded funding rate is malformed".to_string())?;
+    if !recorded.symbol.eq_ignore_ascii_case(&pair) {
+        return Err(format!("Recorded funding rate is for {}, not the requested {}", recorded.symbol, pair));
+    }
+
+    Ok((pair, recorded))
+}
+
+async fn check_recorded_funding_rate(arguments: &Value, output: &Value) -> Result<(), String> {
+    let (pair, recorded) = parse_recorded_funding_rate(arguments, output)?;
+
+    let expected = oracle_service::get_funding_rate(&pair)
+        .await
+        .map_err(|e| format!("Error fetching funding rate: {}", e))?;
+
+    let tolerance = env_or("FUNDING_RATE_TOLERANCE", DEFAULT_FUNDING_RATE_TOLERANCE);
+    if (recorded.funding_rate - expected.funding_rate).abs() > tolerance {
+        return Err(format!(
+            "Recorded funding rate {} of {} differs from {} by more than {}",
+            recorded.funding_rate, pair, expected.funding_rate, tolerance
+        ));
+    }
+    // A funding payment may have passed since the performer's call
+    if recorded.next_funding_time > expected.next_funding_time {
+        return Err(format!("Recorded next funding time of {} is later than {}", pair, expected.next_funding_time));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::inputs::AgentInputs;
+    use crate::handlers::openai::ToolExecutor;
+
+    fn call(name: &str, arguments: Value, output: Value) -> ToolCall {
+        ToolCall { name: name.to_string(), arguments, output }
+    }
+
+    #[tokio::test]
+    async fn replays_each_recorded_call_once() {
+        let tools = ReplayTools::new(vec![
+            call("get_price", json!({ "pair": "ETHUSDT" }), json!({ "price": "1" })),
+            call("get_price", json!({ "pair": "ETHUSDT" }), json!({ "price": "2" })),
+            call("get_funding_rate", json!({ "pair": "ETHUSDT" }), json!({ "funding_rate": 0.0001 })),
+        ]);
+
+        // Repeated calls are served in the recorded order
+        assert_eq!(tools.call("get_price", &json!({ "pair": "ETHUSDT" })).await, json!({ "price": "1" }));
+        assert_eq!(tools.call("get_price", &json!({ "pair": "ETHUSDT" })).await, json!({ "price": "2" }));
+        assert_eq!(tools.unmatched(), 0);
+        assert_eq!(tools.unused(), 1);
+
+        // A call made more often than recorded, or with other arguments, has no output
+        let unmatched = tools.call("get_price", &json!({ "pair": "ETHUSDT" })).await;
+        assert!(unmatched.get("error").is_some());
+        tools.call("get_funding_rate", &json!({ "pair": "BTCUSDT" })).await;
+        assert_eq!(tools.unmatched(), 2);
+        assert_eq!(tools.unused(), 1);
+
+        tools.call("get_funding_rate", &json!({ "pair": "ETHUSDT" })).await;
+        assert_eq!(tools.unused(), 0);
+    }
+
+    #[test]
+    fn refuses_recorded_prices_of_another_pair() {
+        let output = json!({ "symbol": "BTCUSDT", "price": "60000", "timestamp": 1, "method": "last" });
+        let parsed = parse_recorded_price(&json!({ "pair": "ethusdt" }), &output);
+        assert_eq!(parsed.unwrap_err(), "Recorded price is for BTCUSDT, not the requested ETHUSDT");
+
+        let (pair, price, method) = parse_recorded_price(&json!({ "pair": "btcusdt" }), &output).unwrap();
+        assert_eq!((pair.as_str(), price, method), ("BTCUSDT", 60000.0, PriceMethod::Last));
+    }
+
+    #[test]
+    fn refuses_recorded_prices_without_a_method() {
+        let output = json!({ "symbol": "ETHUSDT", "price": "2500", "timestamp": 1 });
+        assert!(parse_recorded_price(&json!({ "pair": "ETHUSDT" }), &output).is_err());
+
+        let output = json!({ "symbol": "ETHUSDT", "price": "2500", "method": "vwap", "trades": 50 });
+        let (_, _, method) = parse_recorded_price(&json!({ "pair": "ETHUSDT" }), &output).unwrap();
+        assert_eq!(method, PriceMethod::Vwap { trades: 50 });
+    }
+
+    #[test]
+    fn refuses_recorded_funding_rates_of_another_pair() {
+        let output = json!({ "symbol": "BTCUSDT", "funding_rate": 0.0001, "next_funding_time": 1 });
+        assert!(parse_recorded_funding_rate(&json!({ "pair": "ETHUSDT" }), &output).is_err());
+        assert!(parse_recorded_funding_rate(&json!({ "pair": "BTCUSDT" }), &output).is_ok());
+        assert!(parse_recorded_funding_rate(&json!({ "pair": "BTCUSDT" }), &json!({ "symbol": "BTCUSDT" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn checks_recorded_balances_and_errors() {
+        let inputs = AgentInputs::parse("1.5 ETH, 2000 USDC", "ETH: 2500").unwrap();
+        let balances = json!({ "holdings": inputs.portfolio.0 });
+        let transcript = vec![
+            call("get_portfolio_balances", json!({}), balances),
+            call("get_portfolio_balances", json!({}), json!({ "holdings": [] })),
+            call("get_portfolio_balances", json!({}), json!({ "error": "unavailable" })),
+            // Errors for invalid arguments or tools are confirmed without market data
+            call("get_price", json!({}), json!({ "error": "Missing `pair` argument" })),
+            call("get_weather", json!({}), json!({ "error": "Unknown tool get_weather" })),
+            call("get_weather", json!({}), json!({ "sunny": true })),
+        ];
+
+        let mismatches = check_recorded_outputs(&transcript, &inputs.portfolio).await;
+        let reasons = mismatches.iter().map(|mismatch| mismatch.reason.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                "Balances differ from the task's portfolio",
+                "Recorded an error for the task's own portfolio",
+                "Recorded an output for unknown tool get_weather",
+            ]
+        );
+    }
+}
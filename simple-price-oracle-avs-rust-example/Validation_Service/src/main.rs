@@ -1,10 +1,18 @@
 use std::env;
-use actix_web::{web, App, HttpServer, Responder, middleware::Logger};
+use std::process;
+use actix_web::{web, App, HttpServer};
+use tracing::{error, info};
+use tracing_actix_web::TracingLogger;
+mod errors;
 mod services;
+use avs_common::{response, settings, telemetry};
 
 mod handlers {
+    pub use avs_common::handlers::{agent_middleware, history, inputs, llm_cache, model_policy, openai, prompts, usage};
+    pub mod tools;
     pub mod task;
-    pub mod openai;
+    pub mod backtest;
+    pub mod health;
 }
 
 // Main function
@@ -13,8 +21,15 @@ async fn main() -> std::io::Result<()> {
     // Load environment variables (if using dotenv)
     dotenv::dotenv().ok();
     
-    // Initialize logger
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // Initialize tracing
+    telemetry::init("validation-service");
+
+    // `--check` runs the self-checks and exits instead of starting the server
+    if env::args().any(|arg| arg == "--check") {
+        let report = services::doctor::run().await;
+        report.print();
+        process::exit(if report.ok { 0 } else { 1 });
+    }
 
     // Get the port from environment variables or default to 4003
     let port: u16 = env::var("PORT")
@@ -22,15 +37,33 @@ async fn main() -> std::io::Result<()> {
         .parse()
         .expect("PORT must be a valid number");
 
+    // Refuse to start with a signing mode performers do not use
+    if let Err(e) = services::eip712::SigningMode::from_env() {
+        error!("Invalid signing mode: {}", e);
+        process::exit(1);
+    }
+
+    // Keep the price cache warm from the Binance trade stream
+    services::price_stream::spawn(services::price_stream::stream_symbols());
 
     // Start the server
-    println!("Server started on port: {}", port);
-    HttpServer::new(|| {
+    info!(port, "Server started");
+    let server = HttpServer::new(|| {
         App::new()
-        .wrap(Logger::default())
+        .wrap(TracingLogger::default())
         .route("/task/validate", web::post().to(handlers::task::validate_agent_task))
+        .route("/task/validate/price", web::post().to(handlers::task::validate_task))
+        .route("/strategy/backtest", web::post().to(handlers::backtest::backtest_strategy))
+        .route("/tasks", web::get().to(handlers::history::get_tasks))
+        .route("/usage", web::get().to(handlers::usage::get_usage))
+        .route("/metrics", web::get().to(handlers::usage::get_metrics))
+        .route("/health", web::get().to(handlers::health::health_check))
+        .route("/ready", web::get().to(handlers::health::ready))
     })
     .bind(("0.0.0.0", port))?
     .run()
-    .await
+    .await;
+
+    telemetry::shutdown();
+    server
 }
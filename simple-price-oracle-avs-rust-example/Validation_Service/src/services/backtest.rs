@@ -0,0 +1,547 @@
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use tracing::warn;
+use crate::handlers::inputs::AgentInputs;
+use crate::services::volatility;
+use crate::settings::{env_opt, env_or};
+
+const DEFAULT_SHOCKS: [f64; 3] = [0.1, 0.2, 0.4];
+const DEFAULT_STABLECOINS: &str = "USDC,USDT,DAI,FDUSD,BUSD,TUSD,USDE";
+const DEFAULT_HISTORY_INTERVAL: &str = "1d";
+const DEFAULT_HISTORY_WINDOW: u32 = 30;
+const DEFAULT_LEVERAGE: f64 = 1.0;
+const DEFAULT_MAINTENANCE_MARGIN: f64 = 0.005;
+
+/// Failure to read or price a strategy.
+#[derive(Debug, thiserror::Error)]
+pub enum BacktestError {
+    #[error("Strategy is not valid JSON with `exchanges` and `positions`: {0}")]
+    Unparsable(String),
+    #[error("Strategy has no positions")]
+    Empty,
+    #[error("No price for {0}, in the task or the strategy")]
+    MissingPrice(String),
+}
+
+impl BacktestError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            BacktestError::Unparsable(_) => "STRATEGY_UNPARSABLE",
+            BacktestError::Empty => "STRATEGY_EMPTY",
+            BacktestError::MissingPrice(_) => "STRATEGY_MISSING_PRICE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Long,
+    Short,
+}
+
+/// One position of a strategy.
+#[derive(Debug, Clone, Serialize)]
+pub struct Leg {
+    pub venue: String,
+    pub token: String,
+    pub direction: Direction,
+    pub amount: f64,
+    pub entry_price: Option<f64>, // the task's price is used when the strategy gives none
+    pub leverage: Option<f64>,
+}
+
+/// Positions proposed by the agent, across venues.
+#[derive(Debug, Clone, Serialize)]
+pub struct Strategy {
+    pub legs: Vec<Leg>,
+}
+
+// Numbers may be given as JSON numbers or as strings, such as `"2500"`
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|number: &f64| number.is_finite())
+}
+
+/// Parses the agent's answer in the `exchanges`/`positions` format of the
+/// `farming_strategy` prompt. Text around the JSON object, such as a code fence, is ignored.
+pub fn parse_strategy(text: &str) -> Result<Strategy, BacktestError> {
+    let unparsable = |reason: &str| BacktestError::Unparsable(reason.to_string());
+    let start = text.find('{').ok_or_else(|| unparsable("no JSON object"))?;
+    let end = text.rfind('}').ok_or_else(|| unparsable("no JSON object"))?;
+    let json: Value = serde_json::from_str(&text[start..=end]).map_err(|e| BacktestError::Unparsable(e.to_string()))?;
+    let exchanges = json["exchanges"].as_array().ok_or_else(|| unparsable("no `exchanges` list"))?;
+
+    let mut legs = Vec::new();
+    for exchange in exchanges {
+        let venue = exchange["target"].as_str().unwrap_or("unknown").to_string();
+        for position in exchange["positions"].as_array().ok_or_else(|| unparsable("an exchange has no `positions` list"))? {
+            let direction = match (position["position"].as_str(), position["side"].as_str()) {
+                (Some("long"), _) | (None, Some("buy")) => Direction::Long,
+                (Some("short"), _) | (None, Some("sell")) => Direction::Short,
+                _ => return Err(unparsable("a position is neither long nor short")),
+            };
+            let token = position["token"].as_str().ok_or_else(|| unparsable("a position has no token"))?;
+            let amount = number(&position["amount"])
+                .filter(|amount| *amount >= 0.0)
+                .ok_or_else(|| BacktestError::Unparsable(format!("{} has no valid amount", token)))?;
+            legs.push(Leg {
+                venue: venue.clone(),
+                token: token.trim().to_uppercase(),
+                direction,
+                amount,
+                entry_price: number(&position["price"]).filter(|price| *price > 0.0),
+                leverage: number(&position["leverage"]).filter(|leverage| *leverage >= 1.0),
+            });
+        }
+    }
+
+    if legs.is_empty() {
+        return Err(BacktestError::Empty);
+    }
+    Ok(Strategy { legs })
+}
+
+/// Relative price moves applied together, such as `ETH: -0.2`. Tokens without a move keep their price.
+#[derive(Debug, Clone, Serialize)]
+pub struct Scenario {
+    pub name: String,
+    pub moves: BTreeMap<String, f64>,
+}
+
+/// Settings of the stress test, read from the service environment.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestConfig {
+    /// Synthetic moves applied up and down to every volatile token (`BACKTEST_SHOCKS`)
+    pub shocks: Vec<f64>,
+    /// Tokens that keep their price in every scenario (`BACKTEST_STABLECOINS`)
+    pub stablecoins: Vec<String>,
+    /// Kline interval of historical scenarios (`BACKTEST_HISTORY_INTERVAL`)
+    pub history_interval: String,
+    /// Klines of history replayed, 0 for none (`BACKTEST_HISTORY_WINDOW`)
+    pub history_window: u32,
+    /// Leverage of positions that do not give one (`BACKTEST_DEFAULT_LEVERAGE`)
+    pub default_leverage: f64,
+    /// Maintenance margin rate of leveraged positions (`BACKTEST_MAINTENANCE_MARGIN`)
+    pub maintenance_margin: f64,
+}
+
+impl BacktestConfig {
+    pub fn from_env() -> Self {
+        let shocks: Vec<f64> = env::var("BACKTEST_SHOCKS")
+            .ok()
+            .map(|shocks| shocks.split(',').filter_map(|shock| shock.trim().parse().ok()).collect())
+            .unwrap_or_else(|| DEFAULT_SHOCKS.to_vec());
+        let stablecoins = env_or("BACKTEST_STABLECOINS", DEFAULT_STABLECOINS.to_string());
+
+        BacktestConfig {
+            shocks: shocks.into_iter().map(f64::abs).filter(|shock| *shock > 0.0).collect(),
+            stablecoins: stablecoins.split(',').map(|token| token.trim().to_uppercase()).filter(|token| !token.is_empty()).collect(),
+            history_interval: env_or("BACKTEST_HISTORY_INTERVAL", DEFAULT_HISTORY_INTERVAL.to_string()),
+            history_window: env_or("BACKTEST_HISTORY_WINDOW", DEFAULT_HISTORY_WINDOW),
+            default_leverage: env_or("BACKTEST_DEFAULT_LEVERAGE", DEFAULT_LEVERAGE).max(1.0),
+            maintenance_margin: env_or("BACKTEST_MAINTENANCE_MARGIN", DEFAULT_MAINTENANCE_MARGIN),
+        }
+    }
+}
+
+/// Every volatile token moved up and down by each shock, such as `-20%` and `+20%`.
+pub fn shock_scenarios(tokens: &[String], shocks: &[f64]) -> Vec<Scenario> {
+    shocks
+        .iter()
+        .flat_map(|shock| [-shock, *shock])
+        .map(|shock| Scenario {
+            name: format!("{:+}%", shock * 100.0),
+            moves: tokens.iter().map(|token| (token.clone(), shock)).collect(),
+        })
+        .collect()
+}
+
+fn kline_time(open_time: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(open_time)
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| open_time.to_string())
+}
+
+/// The moves of each of the last `window` klines, taken across tokens at the same time,
+/// and the move over the whole window. Tokens without history are left out, with a warning.
+pub async fn historical_scenarios(tokens: &[String], interval: &str, window: u32) -> Vec<Scenario> {
+    if window == 0 || tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let pairs: Vec<String> = tokens.iter().map(|token| format!("{}USDT", token)).collect();
+    let histories = join_all(pairs.iter().map(|pair| volatility::klines(pair, interval, window))).await;
+    let mut by_time: BTreeMap<i64, BTreeMap<String, f64>> = BTreeMap::new();
+    let mut whole: BTreeMap<String, f64> = BTreeMap::new();
+    let mut fetched = 0;
+    for (token, history) in tokens.iter().zip(histories) {
+        let history = match history {
+            Ok(history) if !history.is_empty() => history,
+            Ok(_) => {
+                warn!(%token, "No klines, left out of historical scenarios");
+                continue;
+            }
+            Err(e) => {
+                warn!(%token, error = %e, "Could not fetch klines, left out of historical scenarios");
+                continue;
+            }
+        };
+        fetched += 1;
+        for kline in &history {
+            by_time.entry(kline.open_time).or_default().insert(token.clone(), kline.close / kline.open - 1.0);
+        }
+        whole.insert(token.clone(), history[history.len() - 1].close / history[0].open - 1.0);
+    }
+
+    let (Some(first), Some(last)) = (by_time.keys().next().copied(), by_time.keys().last().copied()) else {
+        return Vec::new();
+    };
+    let mut scenarios: Vec<Scenario> = by_time
+        .into_iter()
+        .filter(|(_, moves)| moves.len() == fetched)
+        .map(|(open_time, moves)| Scenario { name: format!("{} {}", interval, kline_time(open_time)), moves })
+        .collect();
+    scenarios.push(Scenario { name: format!("{} to {}", kline_time(first), kline_time(last)), moves: whole });
+    scenarios
+}
+
+/// A leg priced at the task's prices.
+#[derive(Debug, Clone, Serialize)]
+pub struct LegRisk {
+    pub leg: Leg,
+    pub price: f64,             // current price of the token
+    pub entry_price: f64,       // the leg's price, or the current one
+    pub leverage: f64,
+    pub notional_usd: f64,
+    pub delta_usd: f64,         // positive for longs, negative for shorts
+    pub liquidation_price: Option<f64>, // none for unleveraged longs
+    pub liquidation_distance: Option<f64>, // adverse move from the current price to liquidation, negative once past it
+}
+
+/// Outcome of one scenario.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioResult {
+    pub name: String,
+    pub leg_pnl_usd: Vec<f64>, // in the order of the legs
+    pub pnl_usd: f64,
+    pub liquidated: Vec<usize>, // legs whose liquidation price the scenario crosses
+}
+
+/// Risk of a strategy under every scenario.
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskReport {
+    pub legs: Vec<LegRisk>,
+    pub capital_usd: f64, // value of the task's portfolio
+    pub gross_exposure_usd: f64,
+    pub net_delta_usd: f64,
+    pub net_delta_by_token: BTreeMap<String, f64>,
+    pub net_delta_pct: Option<f64>, // of the gross exposure
+    pub min_liquidation_distance: Option<f64>,
+    pub scenarios: Vec<ScenarioResult>,
+    pub worst_scenario: Option<String>,
+    pub worst_loss_usd: f64,
+    pub worst_loss_pct: Option<f64>, // of the capital
+}
+
+/// Prices every leg of `strategy` at `prices` and runs it through `scenarios`.
+///
+/// A leveraged position that a scenario moves past its liquidation price loses
+/// its margin, the notional over the leverage, and no more.
+pub fn run(
+    strategy: &Strategy,
+    prices: &BTreeMap<String, f64>,
+    capital_usd: f64,
+    scenarios: &[Scenario],
+    config: &BacktestConfig,
+) -> Result<RiskReport, BacktestError> {
+    let mut legs = Vec::with_capacity(strategy.legs.len());
+    for leg in &strategy.legs {
+        let price = prices
+            .get(&leg.token)
+            .copied()
+            .or(leg.entry_price)
+            .ok_or_else(|| BacktestError::MissingPrice(leg.token.clone()))?;
+        let entry_price = leg.entry_price.unwrap_or(price);
+        let leverage = leg.leverage.unwrap_or(config.default_leverage);
+        let sign = if leg.direction == Direction::Long { 1.0 } else { -1.0 };
+
+        // Isolated margin: the position is closed once its loss leaves only the maintenance margin
+        let liquidation_price = match leg.direction {
+            Direction::Long if leverage > 1.0 => Some(entry_price * (1.0 - 1.0 / leverage + config.maintenance_margin)),
+            Direction::Long => None,
+            Direction::Short => Some(entry_price * (1.0 + 1.0 / leverage - config.maintenance_margin)),
+        };
+        legs.push(LegRisk {
+            leg: leg.clone(),
+            price,
+            entry_price,
+            leverage,
+            notional_usd: leg.amount * price,
+            delta_usd: sign * leg.amount * price,
+            liquidation_price,
+            liquidation_distance: liquidation_price.map(|liquidation| sign * (price - liquidation) / price),
+        });
+    }
+
+    let results: Vec<ScenarioResult> = scenarios
+        .iter()
+        .map(|scenario| {
+            let mut liquidated = Vec::new();
+            let leg_pnl_usd: Vec<f64> = legs
+                .iter()
+                .enumerate()
+                .map(|(index, risk)| {
+                    let moved = risk.price * (1.0 + scenario.moves.get(&risk.leg.token).copied().unwrap_or(0.0));
+                    let crossed = risk.liquidation_price.is_some_and(|liquidation| match risk.leg.direction {
+                        Direction::Long => moved <= liquidation,
+                        Direction::Short => moved >= liquidation,
+                    });
+                    if crossed {
+                        liquidated.push(index);
+                        return -risk.leg.amount * risk.entry_price / risk.leverage;
+                    }
+                    match risk.leg.direction {
+                        Direction::Long => risk.leg.amount * (moved - risk.entry_price),
+                        Direction::Short => risk.leg.amount * (risk.entry_price - moved),
+                    }
+                })
+                .collect();
+            ScenarioResult {
+                name: scenario.name.clone(),
+                pnl_usd: leg_pnl_usd.iter().sum(),
+                leg_pnl_usd,
+                liquidated,
+            }
+        })
+        .collect();
+
+    let mut net_delta_by_token: BTreeMap<String, f64> = BTreeMap::new();
+    for risk in &legs {
+        *net_delta_by_token.entry(risk.leg.token.clone()).or_default() += risk.delta_usd;
+    }
+    let gross_exposure_usd: f64 = legs.iter().map(|risk| risk.notional_usd).sum();
+    let net_delta_usd: f64 = legs.iter().map(|risk| risk.delta_usd).sum();
+    let worst = results.iter().min_by(|a, b| a.pnl_usd.total_cmp(&b.pnl_usd));
+    let worst_loss_usd = worst.map_or(0.0, |worst| if worst.pnl_usd < 0.0 { -worst.pnl_usd } else { 0.0 });
+
+    Ok(RiskReport {
+        capital_usd,
+        gross_exposure_usd,
+        net_delta_usd,
+        net_delta_by_token,
+        net_delta_pct: (gross_exposure_usd > 0.0).then(|| net_delta_usd.abs() / gross_exposure_usd),
+        min_liquidation_distance: legs.iter().filter_map(|risk| risk.liquidation_distance).min_by(f64::total_cmp),
+        worst_scenario: worst.map(|worst| worst.name.clone()),
+        worst_loss_usd,
+        worst_loss_pct: (capital_usd > 0.0).then(|| worst_loss_usd / capital_usd),
+        scenarios: results,
+        legs,
+    })
+}
+
+// Prices of the task by token, and the value of its portfolio at them
+fn task_prices(inputs: &AgentInputs) -> (BTreeMap<String, f64>, f64) {
+    let prices: BTreeMap<String, f64> = inputs.prices.0.iter().map(|quote| (quote.asset.clone(), quote.price)).collect();
+    let capital_usd = inputs
+        .portfolio
+        .0
+        .iter()
+        .filter_map(|holding| prices.get(&holding.asset).map(|price| holding.amount * price))
+        .sum();
+    (prices, capital_usd)
+}
+
+// Tokens of the strategy that move in scenarios
+fn volatile_tokens(strategy: &Strategy, config: &BacktestConfig) -> Vec<String> {
+    let mut tokens: Vec<String> = strategy.legs.iter().map(|leg| leg.token.clone()).collect();
+    tokens.sort();
+    tokens.dedup();
+    tokens.retain(|token| !config.stablecoins.contains(token));
+    tokens
+}
+
+/// Stress-tests `strategy` at the task's prices under the configured shocks. It
+/// depends on nothing but the task, so every validator reaches the same vote.
+pub fn stress_test(strategy: &Strategy, inputs: &AgentInputs, config: &BacktestConfig) -> Result<RiskReport, BacktestError> {
+    let (prices, capital_usd) = task_prices(inputs);
+    let scenarios = shock_scenarios(&volatile_tokens(strategy, config), &config.shocks);
+    run(strategy, &prices, capital_usd, &scenarios, config)
+}
+
+/// Same as `stress_test`, also replaying the configured Binance history. Klines keep
+/// moving, so this is only reported by `/strategy/backtest` and never decides a vote.
+pub async fn stress_test_with_history(strategy: &Strategy, inputs: &AgentInputs, config: &BacktestConfig) -> Result<RiskReport, BacktestError> {
+    let (prices, capital_usd) = task_prices(inputs);
+    let tokens = volatile_tokens(strategy, config);
+    let mut scenarios = shock_scenarios(&tokens, &config.shocks);
+    scenarios.extend(historical_scenarios(&tokens, &config.history_interval, config.history_window).await);
+    run(strategy, &prices, capital_usd, &scenarios, config)
+}
+
+/// A loss limit a strategy exceeded.
+#[derive(Debug, Clone, Serialize)]
+pub struct Breach {
+    pub limit: &'static str,
+    pub allowed: f64,
+    pub actual: f64,
+}
+
+/// Risk limits of strategies; unset limits are not checked.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Limits {
+    /// Worst-case loss in USD (`BACKTEST_MAX_LOSS_USD`)
+    pub max_loss_usd: Option<f64>,
+    /// Worst-case loss as a fraction of the portfolio value, not checked when the
+    /// portfolio has no priced holdings (`BACKTEST_MAX_LOSS_PCT`)
+    pub max_loss_pct: Option<f64>,
+    /// Net delta as a fraction of the gross exposure (`BACKTEST_MAX_NET_DELTA_PCT`)
+    pub max_net_delta_pct: Option<f64>,
+    /// Smallest relative move any position may be from liquidation (`BACKTEST_MIN_LIQUIDATION_DISTANCE`)
+    pub min_liquidation_distance: Option<f64>,
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        Limits {
+            max_loss_usd: env_opt("BACKTEST_MAX_LOSS_USD"),
+            max_loss_pct: env_opt("BACKTEST_MAX_LOSS_PCT"),
+            max_net_delta_pct: env_opt("BACKTEST_MAX_NET_DELTA_PCT"),
+            min_liquidation_distance: env_opt("BACKTEST_MIN_LIQUIDATION_DISTANCE"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_loss_usd.is_none()
+            && self.max_loss_pct.is_none()
+            && self.max_net_delta_pct.is_none()
+            && self.min_liquidation_distance.is_none()
+    }
+
+    /// Every limit `report` exceeds.
+    pub fn check(&self, report: &RiskReport) -> Vec<Breach> {
+        let above = |limit: &'static str, allowed: Option<f64>, actual: Option<f64>| match (allowed, actual) {
+            (Some(allowed), Some(actual)) if actual > allowed => Some(Breach { limit, allowed, actual }),
+            _ => None,
+        };
+        let below = |limit: &'static str, allowed: Option<f64>, actual: Option<f64>| match (allowed, actual) {
+            (Some(allowed), Some(actual)) if actual < allowed => Some(Breach { limit, allowed, actual }),
+            _ => None,
+        };
+
+        [
+            above("BACKTEST_MAX_LOSS_USD", self.max_loss_usd, Some(report.worst_loss_usd)),
+            above("BACKTEST_MAX_LOSS_PCT", self.max_loss_pct, report.worst_loss_pct),
+            above("BACKTEST_MAX_NET_DELTA_PCT", self.max_net_delta_pct, report.net_delta_pct),
+            below("BACKTEST_MIN_LIQUIDATION_DISTANCE", self.min_liquidation_distance, report.min_liquidation_distance),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            shocks: vec![0.2, 0.4],
+            stablecoins: vec!["USDC".to_string()],
+            history_interval: DEFAULT_HISTORY_INTERVAL.to_string(),
+            history_window: 0,
+            default_leverage: 1.0,
+            maintenance_margin: 0.0,
+        }
+    }
+
+    fn leg(direction: Direction, amount: f64, entry_price: Option<f64>, leverage: Option<f64>) -> Leg {
+        Leg { venue: "test".to_string(), token: "ETH".to_string(), direction, amount, entry_price, leverage }
+    }
+
+    fn eth_at(price: f64) -> BTreeMap<String, f64> {
+        BTreeMap::from([("ETH".to_string(), price)])
+    }
+
+    fn scenarios(config: &BacktestConfig) -> Vec<Scenario> {
+        shock_scenarios(&["ETH".to_string()], &config.shocks)
+    }
+
+    #[test]
+    fn hedged_legs_cancel_out() {
+        let config = config();
+        let strategy = Strategy { legs: vec![leg(Direction::Long, 2.0, None, None), leg(Direction::Short, 2.0, None, None)] };
+
+        let report = run(&strategy, &eth_at(2500.0), 5000.0, &scenarios(&config), &config).unwrap();
+
+        assert_eq!(report.gross_exposure_usd, 10000.0);
+        assert_eq!(report.net_delta_usd, 0.0);
+        assert_eq!(report.net_delta_pct, Some(0.0));
+        assert!(report.scenarios.iter().all(|scenario| scenario.pnl_usd == 0.0));
+        assert_eq!(report.worst_loss_usd, 0.0);
+    }
+
+    #[test]
+    fn reports_the_worst_loss_of_an_open_long() {
+        let config = config();
+        let strategy = Strategy { legs: vec![leg(Direction::Long, 1.0, None, None)] };
+
+        let report = run(&strategy, &eth_at(2000.0), 4000.0, &scenarios(&config), &config).unwrap();
+
+        assert_eq!(report.worst_scenario.as_deref(), Some("-40%"));
+        assert_eq!(report.worst_loss_usd, 800.0);
+        assert_eq!(report.worst_loss_pct, Some(0.2));
+        assert_eq!(report.legs[0].liquidation_price, None);
+    }
+
+    #[test]
+    fn a_liquidated_leg_loses_only_its_margin() {
+        let config = config();
+        // A 4x short at 2000 is liquidated at 2500, a 25% rise
+        let strategy = Strategy { legs: vec![leg(Direction::Short, 1.0, None, Some(4.0))] };
+
+        let report = run(&strategy, &eth_at(2000.0), 2000.0, &scenarios(&config), &config).unwrap();
+
+        assert_eq!(report.legs[0].liquidation_price, Some(2500.0));
+        assert_eq!(report.min_liquidation_distance, Some(0.25));
+        let up_20 = report.scenarios.iter().find(|scenario| scenario.name == "+20%").unwrap();
+        assert!(up_20.liquidated.is_empty());
+        assert_eq!(up_20.pnl_usd, -400.0);
+        let up_40 = report.scenarios.iter().find(|scenario| scenario.name == "+40%").unwrap();
+        assert_eq!(up_40.liquidated, [0]);
+        assert_eq!(up_40.pnl_usd, -500.0);
+    }
+
+    #[test]
+    fn a_leg_past_liquidation_has_a_negative_distance() {
+        let config = config();
+        // Shorted 2x at 1000 and liquidated at 1500, while ETH is at 2000
+        let strategy = Strategy { legs: vec![leg(Direction::Short, 1.0, Some(1000.0), Some(2.0))] };
+
+        let report = run(&strategy, &eth_at(2000.0), 2000.0, &[], &config).unwrap();
+
+        assert_eq!(report.min_liquidation_distance, Some(-0.25));
+        let limits = Limits { min_liquidation_distance: Some(0.1), ..Limits::default() };
+        let breaches = limits.check(&report);
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].limit, "BACKTEST_MIN_LIQUIDATION_DISTANCE");
+    }
+
+    #[test]
+    fn fails_without_a_price() {
+        let config = config();
+        let strategy = Strategy { legs: vec![leg(Direction::Long, 1.0, None, None)] };
+
+        let result = run(&strategy, &BTreeMap::new(), 0.0, &[], &config);
+
+        assert!(matches!(result, Err(BacktestError::MissingPrice(token)) if token == "ETH"));
+    }
+}
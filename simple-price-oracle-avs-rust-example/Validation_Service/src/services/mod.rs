@@ -1,2 +1,5 @@
 pub mod validation_service;
-pub mod oracle_service;
\ No newline at end of file
+pub mod backtest;
+pub mod doctor;
+pub mod volatility;
+pub use avs_common::services::{eip712, merkle, oracle_service, price_stream, task_history, task_meta, usage};
@@ -1,79 +1,343 @@
-use crate::services::oracle_service;
-use reqwest::Error;
+use crate::services::eip712::{self, SignerError, SigningMode};
+use crate::services::merkle;
+use crate::services::task_meta::{ReplayReason, SeenSet, TaskMeta};
+use alloy_primitives::{keccak256, Address, Bytes};
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use crate::services::volatility::{self, ToleranceBand};
+use crate::services::oracle_service::{self, PriceBatchProof, PriceProof};
+use futures_util::future::join_all;
+use serde::Serialize;
 use std::str::FromStr;
-use std::env;
-use anyhow::Result;
-use crate::handlers::openai::{OpenAIAgent, StableYieldFarmingAgent, Message, Agent};
+use crate::settings::env_or;
 
-pub async fn validate(proof_of_task: &str) -> Result<bool, String> {
-    // Convert the proofOfTask string into a float
-    let task_result = match f64::from_str(proof_of_task) {
-        Ok(val) => val,
-        Err(_) => return Err("Invalid proofOfTask value".to_string()),
+/// Failure to validate a task: the request or proof could not be understood.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("{0}")]
+    InvalidProof(String),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error(transparent)]
+    Signer(#[from] SignerError),
+}
+
+impl ValidationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::InvalidProof(_) => "INVALID_PROOF",
+            ValidationError::InvalidRequest(_) => "INVALID_REQUEST",
+            ValidationError::Signer(e) => e.code(),
+        }
+    }
+}
+
+// Recently validated task keys (id and nonce) and proofs, bounded to SEEN_PROOFS_CAPACITY entries each
+static SEEN_TASKS: OnceLock<Mutex<SeenSet>> = OnceLock::new();
+static SEEN_PROOFS: OnceLock<Mutex<SeenSet>> = OnceLock::new();
+const DEFAULT_SEEN_PROOFS_CAPACITY: usize = 10_000;
+
+// Task ids and proofs being validated right now, which are not yet seen
+static IN_FLIGHT: OnceLock<Mutex<InFlight>> = OnceLock::new();
+
+#[derive(Default)]
+struct InFlight {
+    tasks: HashSet<String>,
+    proofs: HashSet<String>,
+}
+
+fn in_flight() -> &'static Mutex<InFlight> {
+    IN_FLIGHT.get_or_init(|| Mutex::new(InFlight::default()))
+}
+
+/// Number of task keys and proofs remembered as seen, and the capacity of each store.
+pub fn seen_counts() -> (usize, usize, usize) {
+    let seen_tasks = seen_set(&SEEN_TASKS).lock().unwrap();
+    let seen_proofs = seen_set(&SEEN_PROOFS).lock().unwrap();
+    (seen_tasks.len(), seen_proofs.len(), seen_tasks.capacity())
+}
+
+/// Number of tasks being validated right now.
+pub fn in_flight_count() -> usize {
+    in_flight().lock().unwrap().tasks.len()
+}
+
+fn seen_set(cell: &'static OnceLock<Mutex<SeenSet>>) -> &'static Mutex<SeenSet> {
+    cell.get_or_init(|| {
+        Mutex::new(SeenSet::new(env_or("SEEN_PROOFS_CAPACITY", DEFAULT_SEEN_PROOFS_CAPACITY)))
+    })
+}
+
+/// A task that passed the replay checks and is being validated. Concurrent
+/// submissions of the same task are refused while it is held. The task is only
+/// remembered as seen once `commit` is called after its vote is recorded;
+/// dropping the claim without committing lets the task be submitted again.
+#[derive(Debug)]
+pub struct ReplayClaim {
+    task_key: String,
+    proof_hash: String,
+}
+
+impl ReplayClaim {
+    pub fn commit(self) {
+        seen_set(&SEEN_TASKS).lock().unwrap().insert(self.task_key.clone());
+        seen_set(&SEEN_PROOFS).lock().unwrap().insert(self.proof_hash.clone());
+    }
+}
+
+impl Drop for ReplayClaim {
+    fn drop(&mut self) {
+        let mut in_flight = in_flight().lock().unwrap();
+        in_flight.tasks.remove(&self.task_key);
+        in_flight.proofs.remove(&self.proof_hash);
+    }
+}
+
+/// Rejects tasks without metadata, expired tasks, and tasks (by id and nonce)
+/// or proofs that were already validated or are being validated.
+pub fn check_replay(meta: Option<&TaskMeta>, proof_of_task: &str) -> Result<ReplayClaim, ReplayReason> {
+    let meta = meta.ok_or(ReplayReason::MissingTaskMetadata)?;
+    if meta.is_expired() {
+        return Err(ReplayReason::ExpiredTask);
+    }
+
+    let task_key = meta.key();
+    let proof_hash = keccak256(proof_of_task.as_bytes()).to_string();
+    let mut in_flight = in_flight().lock().unwrap();
+    if in_flight.tasks.contains(&task_key) || seen_set(&SEEN_TASKS).lock().unwrap().contains(&task_key) {
+        return Err(ReplayReason::DuplicateTask);
+    }
+    if in_flight.proofs.contains(&proof_hash) || seen_set(&SEEN_PROOFS).lock().unwrap().contains(&proof_hash) {
+        return Err(ReplayReason::DuplicateProof);
+    }
+
+    in_flight.tasks.insert(task_key.clone());
+    in_flight.proofs.insert(proof_hash.clone());
+    Ok(ReplayClaim {
+        task_key,
+        proof_hash,
+    })
+}
+
+/// Checks the performer's signature over a task submission, using the
+/// signing mode configured with `SIGNING_MODE`.
+pub fn verify_signature(
+    proof_of_task: &str,
+    data: Option<&str>,
+    performer: &str,
+    task_definition_id: i32,
+    signature: &str,
+) -> Result<bool, ValidationError> {
+    let data = match data {
+        Some(data) => Bytes::from_str(data).map_err(|_| ValidationError::InvalidRequest("Invalid task data".to_string()))?,
+        None => Bytes::new(),
+    };
+    let performer = Address::from_str(performer)
+        .map_err(|_| ValidationError::InvalidRequest("Invalid performer address".to_string()))?;
+
+    Ok(eip712::verify_task_signature(
+        SigningMode::from_env()?,
+        proof_of_task,
+        &data,
+        performer,
+        task_definition_id,
+        signature,
+    )?)
+}
+
+/// Outcome of checking one reported price against the oracle.
+#[derive(Debug, Serialize)]
+pub struct PriceCheck {
+    pub symbol: String,
+    pub reported: String,
+    pub expected: Option<f64>,
+    pub band: Option<ToleranceBand>,
+    pub approved: bool,
+    pub error: Option<String>,
+}
+
+/// Outcome of a batch price task. The task is approved only if every entry is.
+#[derive(Debug, Serialize)]
+pub struct PriceValidation {
+    pub approved: bool,
+    pub checks: Vec<PriceCheck>,
+    pub merkle_root: String,
+    pub root_matches: Option<bool>,
+    pub rejection: Option<ReplayReason>,
+    // Held until the vote on this task is recorded
+    #[serde(skip)]
+    pub claim: Option<ReplayClaim>,
+}
+
+impl PriceValidation {
+    pub fn failed(&self) -> Vec<&str> {
+        self.checks
+            .iter()
+            .filter(|check| !check.approved)
+            .map(|check| check.symbol.as_str())
+            .collect()
+    }
+}
+
+/// Parses a batch price proof.
+fn parse_price_proof(proof_of_task: &str) -> Result<PriceBatchProof, ValidationError> {
+    serde_json::from_str::<PriceBatchProof>(proof_of_task)
+        .map_err(|_| ValidationError::InvalidProof("Invalid proofOfTask value".to_string()))
+}
+
+/// Task id carried in a price proof, used to correlate validation with execution.
+pub fn proof_task_id(proof_of_task: &str) -> Option<String> {
+    parse_price_proof(proof_of_task).ok()?.meta.map(|meta| meta.task_id)
+}
+
+/// Validates every price in the proof independently. When `task_definition_id`
+/// is known, pairs configured for it but missing from the proof fail as well.
+/// When the task result is known, it must be the Merkle root of the prices.
+#[tracing::instrument(name = "validation", skip(proof_of_task))]
+pub async fn validate(
+    proof_of_task: &str,
+    task_definition_id: Option<i32>,
+    result: Option<&str>,
+) -> Result<PriceValidation, ValidationError> {
+    let batch = parse_price_proof(proof_of_task)?;
+    if batch.prices.is_empty() {
+        return Err(ValidationError::InvalidProof("proofOfTask contains no prices".to_string()));
+    }
+
+    let merkle_root = merkle::build_price_tree(&batch.prices)
+        .map_err(ValidationError::InvalidProof)?
+        .1
+        .root()
+        .to_string();
+    let root_matches = result.map(|result| result.eq_ignore_ascii_case(&merkle_root));
+
+    // Duplicates and expired tasks are refused before any price is fetched
+    let claim = match check_replay(batch.meta.as_ref(), proof_of_task) {
+        Ok(claim) => claim,
+        Err(reason) => {
+            return Ok(PriceValidation {
+                approved: false,
+                checks: Vec::new(),
+                merkle_root,
+                root_matches,
+                rejection: Some(reason),
+                claim: None,
+            });
+        }
     };
 
-    // Fetch price details from the Oracle service
-    match oracle_service::get_price("ETHUSDT").await {
-        Ok(oracle_data) => {
-            // Parse price from the oracle response
-            let price_float = match f64::from_str(&oracle_data.price) {
-                Ok(val) => val,
-                Err(_) => return Err("Invalid price data from Oracle".to_string()),
-            };
-
-            // Define upper and lower bounds
-            let upper_bound = price_float * 1.05;
-            let lower_bound = price_float * 0.95;
-
-            // Approve or reject based on price bounds
-            let is_approved = task_result <= upper_bound && task_result >= lower_bound;
-            Ok(is_approved)
+    let mut checks = join_all(batch.prices.iter().map(check_price)).await;
+
+    if let Some(task_definition_id) = task_definition_id {
+        for pair in oracle_service::task_pairs(task_definition_id) {
+            if !batch.prices.iter().any(|proof| proof.symbol == pair) {
+                checks.push(PriceCheck {
+                    symbol: pair,
+                    reported: String::new(),
+                    expected: None,
+                    band: None,
+                    approved: false,
+                    error: Some("Pair missing from proof".to_string()),
+                });
+            }
         }
-        Err(e) => Err(format!("Error fetching price data: {}", e)),
     }
+
+    Ok(PriceValidation {
+        approved: checks.iter().all(|check| check.approved) && root_matches != Some(false),
+        checks,
+        merkle_root,
+        root_matches,
+        rejection: None,
+        claim: Some(claim),
+    })
 }
 
-pub async fn validate_agent(input_prompt: &str, agent_response: &str) -> Result<bool, String> {
-    // Get OpenAI API key from environment variables
-    let api_key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => key,
-        Err(_) => return Err("OPENAI_API_KEY is not set in environment variables".to_string()),
+#[tracing::instrument(name = "price_check", skip(proof), fields(pair = %proof.symbol))]
+async fn check_price(proof: &PriceProof) -> PriceCheck {
+    let mut check = PriceCheck {
+        symbol: proof.symbol.clone(),
+        reported: proof.price.clone(),
+        expected: None,
+        band: None,
+        approved: false,
+        error: None,
     };
 
-    // Create an OpenAI agent
-    let openai_agent = OpenAIAgent::new(api_key, "gpt-4".to_string(), 0.7);
-
-    // Create a StableYieldFarmingAgent with the OpenAI agent
-    let farming_agent = StableYieldFarmingAgent::new(openai_agent);
-    
-    // Prepare messages for validation
-    let messages = vec![
-        Message {
-            role: "user".to_string(),
-            content: input_prompt.to_string(),
-        },
-        Message {
-            role: "assistant".to_string(),
-            content: agent_response.to_string(),
-        },
-        Message {
-            role: "user".to_string(),
-            content: "Is this response accurate, helpful, and following best practices for yield farming? Respond with only 'yes' or 'no'.".to_string(),
-        },
-    ];
-    
-    // Get validation from the agent
-    match farming_agent.chat(messages).await {
-        Ok(response) => {
-            let validation_result = response.response.trim().to_lowercase();
-            if validation_result == "yes" {
-                Ok(true)
-            } else if validation_result == "no" {
-                Ok(false)
-            } else {
-                Err(format!("Unexpected validation response: {}", validation_result))
+    // Convert the reported price into a float
+    let task_result = match f64::from_str(&proof.price) {
+        Ok(val) => val,
+        Err(_) => {
+            check.error = Some("Invalid price value".to_string());
+            return check;
+        }
+    };
+
+    // Fetch price details from the Oracle service using the same methodology
+    let price_float = match oracle_service::get_price_by(&proof.symbol, &proof.method).await {
+        Ok(oracle_data) => match f64::from_str(&oracle_data.price) {
+            Ok(val) => val,
+            Err(_) => {
+                check.error = Some("Invalid price data from Oracle".to_string());
+                return check;
             }
         },
-        Err(e) => Err(format!("Error during agent validation: {}", e)),
+        Err(e) => {
+            check.error = Some(format!("Error fetching price data: {}", e));
+            return check;
+        }
+    };
+
+    // Bounds follow the pair's recent volatility, within its floor and ceiling
+    let band = volatility::tolerance_band(&proof.symbol, price_float).await;
+
+    // Approve or reject based on price bounds
+    check.expected = Some(price_float);
+    check.approved = band.contains(task_result);
+    check.band = Some(band);
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_missing_and_expired_task_metadata() {
+        assert_eq!(check_replay(None, "proof").unwrap_err(), ReplayReason::MissingTaskMetadata);
+
+        let expired = TaskMeta { expires_at: 0, ..TaskMeta::new() };
+        assert_eq!(check_replay(Some(&expired), "expired proof").unwrap_err(), ReplayReason::ExpiredTask);
+    }
+
+    #[test]
+    fn refuses_tasks_and_proofs_seen_before() {
+        let meta = TaskMeta::new();
+        let proof = format!("proof of {}", meta.task_id);
+        check_replay(Some(&meta), &proof).unwrap().commit();
+
+        // Same task id and nonce with another proof
+        let other_proof = format!("other proof of {}", meta.task_id);
+        assert_eq!(check_replay(Some(&meta), &other_proof).unwrap_err(), ReplayReason::DuplicateTask);
+
+        // Same proof under another task
+        assert_eq!(check_replay(Some(&TaskMeta::new()), &proof).unwrap_err(), ReplayReason::DuplicateProof);
+
+        // Same task id with another nonce and proof
+        let renewed = TaskMeta { nonce: meta.nonce + 1, ..meta.clone() };
+        assert!(check_replay(Some(&renewed), &other_proof).is_ok());
+    }
+
+    #[test]
+    fn refuses_tasks_in_flight_until_the_claim_is_dropped() {
+        let meta = TaskMeta::new();
+        let proof = format!("proof of {}", meta.task_id);
+
+        let claim = check_replay(Some(&meta), &proof).unwrap();
+        assert_eq!(check_replay(Some(&meta), &proof).unwrap_err(), ReplayReason::DuplicateTask);
+
+        // Dropping the claim without committing lets the task be submitted again
+        drop(claim);
+        assert!(check_replay(Some(&meta), &proof).is_ok());
     }
 }
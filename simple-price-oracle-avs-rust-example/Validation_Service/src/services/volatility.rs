@@ -0,0 +1,159 @@
+use serde::Serialize;
+use tracing::warn;
+use crate::services::oracle_service::OracleError;
+use crate::settings::{env_opt, env_or};
+
+const BINANCE_API_URL: &str = "https://api.binance.com/api/v3";
+const DEFAULT_INTERVAL: &str = "1m";
+const DEFAULT_WINDOW: u32 = 60;
+const DEFAULT_MULTIPLIER: f64 = 4.0;
+const DEFAULT_FLOOR: f64 = 0.001;
+const DEFAULT_CEILING: f64 = 0.05;
+
+/// Tolerance band a price was checked against, returned so operators can audit the vote.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToleranceBand {
+    pub volatility: Option<f64>, // standard deviation of log returns per interval
+    pub width: f64,              // relative half-width of the band
+    pub floor: f64,
+    pub ceiling: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ToleranceBand {
+    pub fn contains(&self, price: f64) -> bool {
+        price >= self.lower && price <= self.upper
+    }
+}
+
+// Per-pair setting such as `BAND_FLOOR_USDCUSDT`, falling back to `BAND_FLOOR`
+fn pair_setting(name: &str, pair: &str, default: f64) -> f64 {
+    env_opt(&format!("{}_{}", name, pair.to_uppercase())).unwrap_or_else(|| env_or(name, default))
+}
+
+/// Computes the band around `reference` for `pair`.
+///
+/// The width is `VOL_BAND_MULTIPLIER` times the realized volatility of the last
+/// `VOL_WINDOW` klines of `VOL_INTERVAL`, clamped to the pair's floor and ceiling.
+/// When volatility cannot be measured the ceiling is used.
+pub async fn tolerance_band(pair: &str, reference: f64) -> ToleranceBand {
+    let floor = pair_setting("BAND_FLOOR", pair, DEFAULT_FLOOR);
+    let ceiling = pair_setting("BAND_CEILING", pair, DEFAULT_CEILING).max(floor);
+    let multiplier = pair_setting("VOL_BAND_MULTIPLIER", pair, DEFAULT_MULTIPLIER);
+
+    let volatility = match realized_volatility(pair).await {
+        Ok(volatility) => Some(volatility),
+        Err(e) => {
+            warn!(pair, error = %e, "Could not compute volatility, using the band ceiling");
+            None
+        }
+    };
+    band(reference, volatility, multiplier, floor, ceiling)
+}
+
+fn band(reference: f64, volatility: Option<f64>, multiplier: f64, floor: f64, ceiling: f64) -> ToleranceBand {
+    let width = volatility.map_or(ceiling, |volatility| (volatility * multiplier).clamp(floor, ceiling));
+
+    ToleranceBand {
+        volatility,
+        width,
+        floor,
+        ceiling,
+        lower: reference * (1.0 - width),
+        upper: reference * (1.0 + width),
+    }
+}
+
+/// One Binance kline: its open time in milliseconds and its open and close prices.
+#[derive(Debug, Clone, Copy)]
+pub struct Kline {
+    pub open_time: i64,
+    pub open: f64,
+    pub close: f64,
+}
+
+// Prices come as strings such as `"2500.10"`
+fn kline_price(value: Option<&serde_json::Value>) -> Option<f64> {
+    value
+        .and_then(|price| price.as_str())
+        .and_then(|price| price.parse::<f64>().ok())
+        .filter(|price| price.is_finite() && *price > 0.0)
+}
+
+/// The last `limit` klines of `interval` of a Binance pair such as `ETHUSDT`, oldest first.
+pub async fn klines(pair: &str, interval: &str, limit: u32) -> Result<Vec<Kline>, OracleError> {
+    let url = format!(
+        "{}/klines?symbol={}&interval={}&limit={}",
+        BINANCE_API_URL,
+        pair.to_uppercase(),
+        interval,
+        limit
+    );
+
+    // Each kline is [openTime, open, high, low, close, ...] with prices as strings
+    let klines: Vec<Vec<serde_json::Value>> = reqwest::get(&url).await?.error_for_status()?.json().await?;
+    klines
+        .iter()
+        .map(|kline| {
+            let open_time = kline.first().and_then(|time| time.as_i64());
+            match (open_time, kline_price(kline.get(1)), kline_price(kline.get(4))) {
+                (Some(open_time), Some(open), Some(close)) => Ok(Kline { open_time, open, close }),
+                _ => Err(OracleError::InvalidData(format!("Invalid kline for {}", pair))),
+            }
+        })
+        .collect()
+}
+
+async fn realized_volatility(pair: &str) -> Result<f64, OracleError> {
+    let interval = env_or("VOL_INTERVAL", DEFAULT_INTERVAL.to_string());
+    let window = env_or("VOL_WINDOW", DEFAULT_WINDOW);
+    let closes: Vec<f64> = klines(pair, &interval, window + 1).await?.iter().map(|kline| kline.close).collect();
+    volatility_of(&closes).ok_or_else(|| OracleError::InsufficientHistory(pair.to_string()))
+}
+
+/// Standard deviation of log returns between consecutive closes, or `None` with fewer than two returns.
+fn volatility_of(closes: &[f64]) -> Option<f64> {
+    let returns: Vec<f64> = closes.windows(2).map(|pair| (pair[1] / pair[0]).ln()).collect();
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn measures_the_sample_deviation_of_log_returns() {
+        // Log returns of ln(2), 0 and -ln(2): mean 0, sample variance 2 ln(2)^2 / 2
+        let volatility = volatility_of(&[100.0, 200.0, 200.0, 100.0]).unwrap();
+        assert!(close(volatility, 2f64.ln()), "{}", volatility);
+
+        assert_eq!(volatility_of(&[100.0, 101.0, 102.01]), Some(0.0));
+        assert_eq!(volatility_of(&[100.0, 101.0]), None);
+        assert_eq!(volatility_of(&[]), None);
+    }
+
+    #[test]
+    fn clamps_the_band_to_the_floor_and_ceiling() {
+        let inside = band(100.0, Some(0.005), 4.0, 0.001, 0.05);
+        assert!(close(inside.width, 0.02));
+        assert!(close(inside.lower, 98.0) && close(inside.upper, 102.0));
+        assert!(inside.contains(101.0) && !inside.contains(102.5));
+
+        assert!(close(band(100.0, Some(0.0), 4.0, 0.001, 0.05).width, 0.001));
+        assert!(close(band(100.0, Some(0.1), 4.0, 0.001, 0.05).width, 0.05));
+        // Without a measured volatility the ceiling is used
+        let unmeasured = band(100.0, None, 4.0, 0.001, 0.05);
+        assert!(close(unmeasured.width, 0.05) && unmeasured.volatility.is_none());
+    }
+}